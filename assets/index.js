@@ -2,18 +2,29 @@ document.addEventListener('DOMContentLoaded', function () {
   const contentContainer = document.getElementById('feed-item');
   const likeBtn = document.getElementById('like');
   const dislikeBtn = document.getElementById('dislike');
+  // the entry on screen, which a vote is about
+  let shown = null;
 
   async function fetchNextContent(is_liked) {
-    const response = await fetch('/next', {
+    // e.g. /?order=score picks the order of the queue
+    const response = await fetch('/next' + window.location.search, {
       method: 'POST',
       headers: {
         'Content-Type': 'application/json',
       },
-      body: JSON.stringify({ liked: is_liked }),
+      body: JSON.stringify({
+        liked: shown ? is_liked : null,
+        feed_url: shown?.feedUrl,
+        id: shown?.id,
+      }),
     });
     if (response.ok) {
       window.scrollTo({ top: 0, behavior: 'smooth' });
       const content = await response.text();
+      const template = document.createElement('template');
+      template.innerHTML = content;
+      const entry = template.content.querySelector('[data-id]');
+      shown = entry ? { feedUrl: entry.dataset.feedUrl, id: entry.dataset.id } : null;
       setTimeout(() => {
         contentContainer.innerHTML = content;
       }, 200);
@@ -66,6 +77,13 @@ function createFeedItem(feed) {
   });
 
   li.appendChild(removeButton);
+
+  if (!feed.ok) {
+    const status = document.createElement('div');
+    status.className = 'feed-status';
+    status.textContent = feed.status;
+    li.appendChild(status);
+  }
   return li;
 }
 
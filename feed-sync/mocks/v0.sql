@@ -0,0 +1,27 @@
+-- FeedHistory.db as written before schema versioning existed. The second
+-- feed never synced, so its id is still empty.
+CREATE TABLE already_seen (
+    id TEXT PRIMARY KEY,
+    title TEXT,
+    authors TEXT,
+    content TEXT,
+    links TEXT,
+    summary TEXT,
+    categories TEXT,
+    language TEXT,
+    is_liked INTEGER
+);
+CREATE TABLE feeds (
+    id TEXT PRIMARY KEY,
+    url TEXT
+);
+
+INSERT INTO already_seen VALUES (
+    'https://a.example/1', 'rust 1.76 released', '["jane"]', '"<p>release notes</p>"',
+    '"https://a.example/1"', 'the rust team is happy', '["rust"]', 'en', 1
+);
+INSERT INTO already_seen VALUES (
+    'https://a.example/2', 'free crypto', '[]', '""', '"https://a.example/2"', '', '[]', '', 0
+);
+INSERT INTO feeds VALUES ('a-feed', 'https://a.example/rss');
+INSERT INTO feeds VALUES ('', 'https://b.example/rss');
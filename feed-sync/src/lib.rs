@@ -1,11 +1,23 @@
 pub mod parser;
 pub mod persistence;
+pub mod ranking;
+pub mod scheduler;
+pub mod schema;
+pub mod scoring;
 
 use feed_rs::model::{Entry, Feed};
-use reqwest::get;
+use persistence::Unsaved;
+use reqwest::header::{ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+use reqwest::{Client, StatusCode};
+use scoring::{Score, ScoreHook};
 use tokio::task;
 
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const FETCH_TIMEOUT: Duration = Duration::from_secs(15);
+const DEFAULT_RETENTION: Duration = Duration::from_secs(7 * 24 * 60 * 60);
 
 // Define your structs here
 
@@ -13,56 +25,336 @@ type IsLiked = bool;
 #[derive(Debug, Clone)]
 pub struct FeedManager {
     pub feeds: HashSet<(Feed, String)>,
-    pub to_see: Vec<Entry>,
-    pub already_seen: Vec<(Entry, IsLiked)>,
+    pub to_see: Vec<QueuedEntry>,
+    pub already_seen: Vec<(QueuedEntry, IsLiked)>,
+    /// Entries the classifier hid from the queue, with their latest score,
+    /// kept until they are voted on or expire like queued ones so misjudged
+    /// ones can still be rescued.
+    pub filtered: Vec<(QueuedEntry, f64)>,
+    pub feed_meta: HashMap<String, FeedMeta>,
+    pub last_sync: SyncReport,
+    /// How long a queued entry survives after its feed stopped listing it.
+    pub retention: Duration,
+    /// Scores entries as they are queued.
+    pub scorer: Option<ScoreHook>,
+    /// Times [`FeedManager::publish_scores`] stored a new set of scores,
+    /// saved as the `model_version` of every score.
+    pub model_version: i64,
+    /// Entry changes [`FeedManager::save_entries`] has yet to write.
+    unsaved: Unsaved,
 }
 unsafe impl Send for FeedManager {}
 
+/// An entry together with the feed it was fetched from. Entries are keyed by
+/// feed url and entry id, see [`QueuedEntry::key`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueuedEntry {
+    pub entry: Entry,
+    pub feed_url: String,
+    /// Unix timestamp of the first fetch that returned this entry.
+    pub fetched_at: i64,
+    /// Unix timestamp of the first fetch whose feed no longer listed this entry.
+    pub missing_since: Option<i64>,
+    /// Given by [`FeedManager::scorer`] when the entry was queued, or since.
+    pub score: Option<Score>,
+    /// Place in the order `sync` queued entries in, kept however the queue
+    /// is ranked.
+    pub seq: i64,
+}
+
+impl QueuedEntry {
+    /// Also records `feed_url` as the entry's [`Entry::source`], which
+    /// feed-rs leaves empty, so code handed just the entry knows its feed.
+    pub fn new(mut entry: Entry, feed_url: &str) -> Self {
+        entry.source = Some(feed_url.to_string());
+        QueuedEntry {
+            entry,
+            feed_url: feed_url.to_string(),
+            fetched_at: unix_now(),
+            missing_since: None,
+            score: None,
+            seq: 0,
+        }
+    }
+
+    pub fn key(&self) -> (&str, &str) {
+        (&self.feed_url, &self.entry.id)
+    }
+}
+
+/// HTTP cache validators and fetch bookkeeping kept for each feed url.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FeedMeta {
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    /// Unix timestamp, in seconds, of the last successful fetch.
+    pub fetched_at: Option<i64>,
+    /// Per-feed refresh interval in seconds, overriding the feed's `ttl`.
+    pub sync_interval: Option<u64>,
+}
+
+/// Outcome of fetching a single feed during a [`FeedManager::sync`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SyncStatus {
+    Synced,
+    NotModified,
+    HttpStatus(u16),
+    Timeout,
+    Network(String),
+    Parse(String),
+}
+
+impl SyncStatus {
+    pub fn is_ok(&self) -> bool {
+        matches!(self, SyncStatus::Synced | SyncStatus::NotModified)
+    }
+}
+
+impl fmt::Display for SyncStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SyncStatus::Synced => write!(f, "ok"),
+            SyncStatus::NotModified => write!(f, "not modified"),
+            SyncStatus::HttpStatus(code) => write!(f, "HTTP status {}", code),
+            SyncStatus::Timeout => write!(f, "timed out"),
+            SyncStatus::Network(e) => write!(f, "network error: {}", e),
+            SyncStatus::Parse(e) => write!(f, "parse error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for SyncStatus {}
+
+/// Per-feed outcomes of the last sync, keyed by feed url.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SyncReport {
+    pub outcomes: Vec<(String, SyncStatus)>,
+}
+
+impl SyncReport {
+    pub fn status(&self, url: &str) -> Option<&SyncStatus> {
+        self.outcomes.iter().find(|(u, _)| u == url).map(|(_, s)| s)
+    }
+
+    pub fn failures(&self) -> impl Iterator<Item = &(String, SyncStatus)> {
+        self.outcomes.iter().filter(|(_, s)| !s.is_ok())
+    }
+
+    pub fn is_ok(&self) -> bool {
+        self.failures().next().is_none()
+    }
+
+    /// Replaces the outcomes of the feeds `other` covers, keeping the rest.
+    pub fn merge(&mut self, other: &SyncReport) {
+        for (url, status) in &other.outcomes {
+            match self.outcomes.iter_mut().find(|(u, _)| u == url) {
+                Some((_, old)) => *old = status.clone(),
+                None => self.outcomes.push((url.clone(), status.clone())),
+            }
+        }
+    }
+}
+
+impl Default for FeedManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl FeedManager {
     pub fn new() -> Self {
         FeedManager {
             feeds: HashSet::new(),
             to_see: Vec::new(),
             already_seen: Vec::new(),
+            filtered: Vec::new(),
+            feed_meta: HashMap::new(),
+            last_sync: SyncReport::default(),
+            retention: DEFAULT_RETENTION,
+            scorer: None,
+            model_version: 0,
+            unsaved: Unsaved::default(),
         }
     }
 
     pub async fn new_feed(&mut self, url: &str) -> Result<Feed, Box<dyn std::error::Error>> {
+        let jobs = self.start_new_feed(url);
+        let results = fetch_feeds(jobs).await;
+        self.finish_new_feed(url, results)
+    }
+
+    /// Adds `url` as a feed not fetched yet and snapshots the job fetching
+    /// it, like [`FeedManager::sync_jobs`]. The fetch is then handed to
+    /// [`FeedManager::finish_new_feed`].
+    pub fn start_new_feed(&mut self, url: &str) -> Vec<SyncJob> {
         self.add_feed(default_feed(), url.to_string());
-        self.sync().await;
-        let feed = self.get_feed(url).unwrap().clone();
-        Ok(feed)
+        self.sync_jobs(Some(&[url.to_string()]))
     }
 
-    pub async fn sync(&mut self) {
-        let mut new_feeds = HashSet::new();
-        self.to_see.clear();
+    /// Applies the first fetch of a feed added by
+    /// [`FeedManager::start_new_feed`], removing the feed again if it failed.
+    pub fn finish_new_feed(
+        &mut self,
+        url: &str,
+        results: Vec<SyncResult>,
+    ) -> Result<Feed, Box<dyn std::error::Error>> {
+        let report = self.apply_sync(results);
+        if let Some(status) = report.status(url).filter(|s| !s.is_ok()) {
+            self.remove_feed_by_url(url);
+            return Err(Box::new(status.clone()));
+        }
+        let feed = self
+            .get_feed(url)
+            .ok_or("feed removed while it was fetched")?
+            .clone();
+        Ok(feed)
+    }
 
-        let mut tasks = Vec::new();
+    /// Fetches every feed concurrently. A feed that fails keeps the last
+    /// version that was fetched successfully, and its entries stay queued.
+    pub async fn sync(&mut self) -> SyncReport {
+        let jobs = self.sync_jobs(None);
+        let results = fetch_feeds(jobs).await;
+        self.apply_sync(results)
+    }
 
-        for (_, url) in self.feeds.iter() {
-            let url = url.clone();
+    /// Snapshots what is needed to fetch `urls`, or every feed when `None`,
+    /// so the network round trip can run without holding on to the manager.
+    pub fn sync_jobs(&self, urls: Option<&[String]>) -> Vec<SyncJob> {
+        let with_entries: HashSet<&str> = self
+            .to_see
+            .iter()
+            .chain(self.already_seen.iter().map(|(e, _)| e))
+            .chain(self.filtered.iter().map(|(e, _)| e))
+            .map(|e| e.feed_url.as_str())
+            .collect();
+        self.feeds
+            .iter()
+            .filter(|(_, url)| urls.is_none_or(|urls| urls.contains(url)))
+            .map(|(feed, url)| {
+                // validators are only useful while we still hold what they
+                // describe: a placeholder feed loaded from the db has its
+                // entries loaded too, or must be refetched
+                let meta = if *feed == default_feed() && !with_entries.contains(url.as_str()) {
+                    FeedMeta::default()
+                } else {
+                    self.feed_meta.get(url).cloned().unwrap_or_default()
+                };
+                SyncJob {
+                    url: url.clone(),
+                    meta,
+                }
+            })
+            .collect()
+    }
 
-            let task = task::spawn(async move {
-                let xml = get(&url).await.unwrap().text().await.unwrap();
-                let new_feed = feed_rs::parser::parse(xml.as_bytes()).unwrap();
-                (new_feed, url)
-            });
+    /// Folds fetched feeds back in and merges their entries into the queue.
+    /// Results for feeds removed while they were being fetched are dropped.
+    pub fn apply_sync(&mut self, results: Vec<SyncResult>) -> SyncReport {
+        let mut report = SyncReport::default();
 
-            tasks.push(task);
+        for SyncResult { url, fetched } in results {
+            let Some(old_feed) = self.get_feed(&url).cloned() else {
+                continue;
+            };
+            let (new_feed, status) = match fetched {
+                Ok(Fetched::Feed(feed, meta)) => {
+                    let sync_interval = self.feed_meta.get(&url).and_then(|m| m.sync_interval);
+                    self.feed_meta.insert(
+                        url.clone(),
+                        FeedMeta {
+                            sync_interval,
+                            ..meta
+                        },
+                    );
+                    (*feed, SyncStatus::Synced)
+                }
+                Ok(Fetched::NotModified) => {
+                    let meta = self.feed_meta.entry(url.clone()).or_default();
+                    meta.fetched_at = Some(unix_now());
+                    (old_feed, SyncStatus::NotModified)
+                }
+                Err(status) => (old_feed, status),
+            };
+            // a 304 means nothing changed, and the feed kept may be a
+            // placeholder loaded from the db that lists no entries at all
+            if status == SyncStatus::Synced {
+                self.merge_entries(&url, &new_feed.entries);
+            }
+            self.feeds.retain(|(_, u)| *u != url);
+            self.feeds.insert((new_feed, url.clone()));
+            report.outcomes.push((url, status));
         }
 
-        for task in tasks {
-            let (new_feed, url) = task.await.unwrap();
-            for entry in &new_feed.entries {
-                if !self.to_see.contains(&entry) {
-                    self.to_see.push(entry.clone());
+        self.last_sync.merge(&report);
+        report
+    }
+
+    /// Appends the entries of `url` that were never queued nor judged, and
+    /// drops queued and filtered ones the feed stopped listing more than
+    /// `retention` ago. The order of the existing queue is kept.
+    fn merge_entries(&mut self, url: &str, entries: &[Entry]) {
+        let now = unix_now();
+        let listed: HashSet<&str> = entries.iter().map(|e| e.id.as_str()).collect();
+        let mut known: HashSet<String> = self
+            .to_see
+            .iter()
+            .chain(self.already_seen.iter().map(|(e, _)| e))
+            .chain(self.filtered.iter().map(|(e, _)| e))
+            .filter(|e| e.feed_url == url)
+            .map(|e| e.entry.id.clone())
+            .collect();
+
+        let retention = self.retention.as_secs() as i64;
+        let unsaved = &mut self.unsaved;
+        let mut keep = |queued: &mut QueuedEntry| {
+            if queued.feed_url != url {
+                return true;
+            }
+            if listed.contains(queued.entry.id.as_str()) {
+                if queued.missing_since.take().is_some() {
+                    unsaved.row(queued);
                 }
+                return true;
             }
-            new_feeds.insert((new_feed, url));
-        }
+            let missing_since = match queued.missing_since {
+                Some(since) => since,
+                None => {
+                    queued.missing_since = Some(now);
+                    unsaved.row(queued);
+                    now
+                }
+            };
+            let kept = now - missing_since < retention;
+            if !kept {
+                unsaved.removed(queued);
+            }
+            kept
+        };
+        self.to_see.retain_mut(|queued| keep(queued));
+        self.filtered.retain_mut(|(queued, _)| keep(queued));
 
-        self.feeds = new_feeds;
+        let mut seq = self
+            .to_see
+            .iter()
+            .chain(self.filtered.iter().map(|(e, _)| e))
+            .map(|e| e.seq + 1)
+            .max()
+            .unwrap_or(0);
+        for entry in entries {
+            if known.insert(entry.id.clone()) {
+                let mut queued = QueuedEntry::new(entry.clone(), url);
+                queued.seq = seq;
+                seq += 1;
+                // scored as queued, `source` included, like every later score
+                queued.score = self.scorer.as_ref().map(|hook| Score {
+                    value: hook.0.score(&queued.entry),
+                    model_version: self.model_version,
+                });
+                self.unsaved.row(&queued);
+                self.to_see.push(queued);
+            }
+        }
     }
 
     fn add_feed(&mut self, feed: Feed, url: String) {
@@ -71,10 +363,158 @@ impl FeedManager {
 
     pub fn remove_feed_by_url(&mut self, url: &str) {
         self.feeds.retain(|(_, u)| u != url);
+        self.to_see.retain(|queued| queued.feed_url != url);
+        self.filtered.retain(|(queued, _)| queued.feed_url != url);
+        self.feed_meta.remove(url);
+        self.last_sync.outcomes.retain(|(u, _)| u != url);
     }
     pub fn get_feed(&self, url: &str) -> Option<&Feed> {
         self.feeds.iter().find(|(_, u)| u == url).map(|(f, _)| f)
     }
+
+    /// Moves the entry at the end of the queue, the one that would be shown
+    /// next, to `filtered` with the score that hid it.
+    pub fn filter_last(&mut self, score: f64) {
+        if let Some(queued) = self.to_see.pop() {
+            self.unsaved.score(&queued);
+            self.filtered.push((queued, score));
+        }
+    }
+
+    /// Takes a queued entry out, e.g. to vote on the one that was shown
+    /// while syncs kept appending to the queue.
+    pub fn take_queued(&mut self, feed_url: &str, id: &str) -> Option<QueuedEntry> {
+        let index = self
+            .to_see
+            .iter()
+            .position(|queued| queued.key() == (feed_url, id))?;
+        Some(self.to_see.remove(index))
+    }
+
+    /// Takes a filtered entry out, e.g. to vote on it.
+    pub fn take_filtered(&mut self, feed_url: &str, id: &str) -> Option<QueuedEntry> {
+        let index = self
+            .filtered
+            .iter()
+            .position(|(queued, _)| queued.key() == (feed_url, id))?;
+        Some(self.filtered.remove(index).0)
+    }
+}
+
+/// A feed to fetch, see [`FeedManager::sync_jobs`].
+#[derive(Debug, Clone)]
+pub struct SyncJob {
+    pub url: String,
+    meta: FeedMeta,
+}
+
+/// The outcome of a [`SyncJob`], see [`FeedManager::apply_sync`].
+pub struct SyncResult {
+    pub url: String,
+    fetched: Result<Fetched, SyncStatus>,
+}
+
+enum Fetched {
+    Feed(Box<Feed>, FeedMeta),
+    NotModified,
+}
+
+/// Fetches every job concurrently.
+pub async fn fetch_feeds(jobs: Vec<SyncJob>) -> Vec<SyncResult> {
+    let client = Client::builder()
+        .timeout(FETCH_TIMEOUT)
+        .build()
+        .expect("failed to build http client");
+    let mut tasks = Vec::new();
+
+    for SyncJob { url, meta } in jobs {
+        let client = client.clone();
+        let task_url = url.clone();
+        let task = task::spawn(async move { fetch_feed(&client, &task_url, meta).await });
+        tasks.push((url, task));
+    }
+
+    let mut results = Vec::new();
+    for (url, task) in tasks {
+        let fetched = task
+            .await
+            .unwrap_or_else(|e| Err(SyncStatus::Network(e.to_string())));
+        results.push(SyncResult { url, fetched });
+    }
+    results
+}
+
+async fn fetch_feed(client: &Client, url: &str, meta: FeedMeta) -> Result<Fetched, SyncStatus> {
+    let mut request = client.get(url);
+    if let Some(etag) = &meta.etag {
+        request = request.header(IF_NONE_MATCH, etag);
+    }
+    if let Some(last_modified) = &meta.last_modified {
+        request = request.header(IF_MODIFIED_SINCE, last_modified);
+    }
+
+    let response = request.send().await.map_err(request_error)?;
+    if response.status() == StatusCode::NOT_MODIFIED {
+        return Ok(Fetched::NotModified);
+    }
+    if !response.status().is_success() {
+        return Err(SyncStatus::HttpStatus(response.status().as_u16()));
+    }
+
+    let header = |name| {
+        response
+            .headers()
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string)
+    };
+    let meta = FeedMeta {
+        etag: header(ETAG),
+        last_modified: header(LAST_MODIFIED),
+        fetched_at: Some(unix_now()),
+        sync_interval: None,
+    };
+    let xml = response.bytes().await.map_err(request_error)?;
+    let feed =
+        feed_rs::parser::parse(xml.as_ref()).map_err(|e| SyncStatus::Parse(e.to_string()))?;
+    Ok(Fetched::Feed(Box::new(feed), meta))
+}
+
+fn request_error(e: reqwest::Error) -> SyncStatus {
+    if e.is_timeout() {
+        SyncStatus::Timeout
+    } else {
+        SyncStatus::Network(e.to_string())
+    }
+}
+
+pub(crate) fn unix_now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() as i64)
+}
+
+fn default_feed() -> Feed {
+    Feed {
+        id: "".to_string(),
+        title: None,
+        updated: None,
+        authors: vec![],
+        links: vec![],
+        categories: vec![],
+        contributors: vec![],
+        generator: None,
+        icon: None,
+        logo: None,
+        rights: None,
+        entries: vec![],
+        language: None,
+        feed_type: feed_rs::model::FeedType::Atom,
+        description: None,
+        published: None,
+        rating: None,
+        ttl: None,
+    }
 }
 
 #[cfg(test)]
@@ -86,7 +526,7 @@ mod tests {
         let (_mock, mg) = _build_from_mock().await;
         let addr = _mock.host_with_port();
         let addr = &format!("http://{}", addr);
-        let feed = mg.get_feed(&addr);
+        let feed = mg.get_feed(addr);
 
         assert!(feed.is_some());
     }
@@ -116,6 +556,242 @@ mod tests {
         assert!(mg.feeds.is_empty());
     }
 
+    #[tokio::test]
+    async fn test_sync_keeps_last_good_feed_on_error() {
+        let (mut _mock, mut mg) = _build_from_mock().await;
+        let addr = format!("http://{}", _mock.host_with_port());
+        let good = mg.get_feed(&addr).unwrap().clone();
+
+        _mock.reset();
+        _mock.mock("GET", "/").with_status(503).create_async().await;
+        let report = mg.sync().await;
+
+        assert_eq!(report.status(&addr), Some(&SyncStatus::HttpStatus(503)));
+        assert_eq!(mg.get_feed(&addr), Some(&good));
+        assert_eq!(mg.to_see.len(), good.entries.len());
+        assert_eq!(mg.last_sync, report);
+    }
+
+    #[tokio::test]
+    async fn test_sync_reports_parse_error() {
+        let (mut _mock, mut mg) = _build_from_mock().await;
+        let addr = format!("http://{}", _mock.host_with_port());
+
+        _mock.reset();
+        _mock
+            .mock("GET", "/")
+            .with_status(200)
+            .with_body("<rss><channel>")
+            .create_async()
+            .await;
+        let report = mg.sync().await;
+
+        assert!(matches!(report.status(&addr), Some(SyncStatus::Parse(_))));
+        assert!(!report.is_ok());
+        assert!(mg.get_feed(&addr).is_some());
+    }
+
+    #[tokio::test]
+    async fn test_new_feed_rejects_unreachable_feed() {
+        let mut _m = mockito::Server::new_async().await;
+        _m.mock("GET", "/").with_status(404).create_async().await;
+        let addr = format!("http://{}", _m.host_with_port());
+
+        let mut mg = FeedManager::new();
+        let result = mg.new_feed(&addr).await;
+
+        assert!(result.is_err());
+        assert!(mg.feeds.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_sync_sends_validators_and_skips_not_modified() {
+        let mut _m = mockito::Server::new_async().await;
+        _m.mock("GET", "/")
+            .with_status(200)
+            .with_header("content-type", "application/rss+xml")
+            .with_header("etag", "\"v1\"")
+            .with_header("last-modified", "Wed, 21 Oct 2015 07:28:00 GMT")
+            .with_body(_RSS)
+            .create_async()
+            .await;
+        let not_modified = _m
+            .mock("GET", "/")
+            .match_header("if-none-match", "\"v1\"")
+            .match_header("if-modified-since", "Wed, 21 Oct 2015 07:28:00 GMT")
+            .with_status(304)
+            .create_async()
+            .await;
+        let addr = format!("http://{}", _m.host_with_port());
+
+        let mut mg = FeedManager::new();
+        mg.new_feed(&addr).await.unwrap();
+        let meta = mg.feed_meta[&addr].clone();
+        assert_eq!(meta.etag.as_deref(), Some("\"v1\""));
+        assert!(meta.fetched_at.is_some());
+
+        let feed = mg.get_feed(&addr).unwrap().clone();
+        let report = mg.sync().await;
+
+        not_modified.assert_async().await;
+        assert_eq!(report.status(&addr), Some(&SyncStatus::NotModified));
+        assert_eq!(mg.get_feed(&addr), Some(&feed));
+        assert_eq!(mg.to_see.len(), feed.entries.len());
+    }
+
+    #[tokio::test]
+    async fn test_partial_sync_only_fetches_requested_feeds() {
+        let (mut _mock, mut mg) = _build_from_mock().await;
+        let addr = format!("http://{}", _mock.host_with_port());
+        mg.add_feed(default_feed(), "http://127.0.0.1:1/unreachable".to_string());
+
+        let jobs = mg.sync_jobs(Some(std::slice::from_ref(&addr)));
+        assert_eq!(jobs.len(), 1);
+        let report = mg.apply_sync(fetch_feeds(jobs).await);
+
+        assert_eq!(report.outcomes, vec![(addr.clone(), SyncStatus::Synced)]);
+        assert_eq!(mg.feeds.len(), 2);
+        assert!(mg
+            .last_sync
+            .status("http://127.0.0.1:1/unreachable")
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn test_sync_merges_new_entries_into_queue() {
+        let mut _m = mockito::Server::new_async().await;
+        _m.mock("GET", "/")
+            .with_body(_rss_with_items(&["a", "b"]))
+            .create_async()
+            .await;
+        let addr = format!("http://{}", _m.host_with_port());
+        let mut mg = FeedManager::new();
+        mg.new_feed(&addr).await.unwrap();
+        let judged = mg.to_see.remove(0);
+        mg.already_seen.push((judged, true));
+        let queue = mg.to_see.clone();
+
+        _m.reset();
+        _m.mock("GET", "/")
+            .with_body(_rss_with_items(&["c", "a", "b"]))
+            .create_async()
+            .await;
+        mg.sync().await;
+
+        let ids: Vec<&str> = mg.to_see.iter().map(|e| e.entry.id.as_str()).collect();
+        assert_eq!(ids, ["b", "c"]);
+        assert_eq!(mg.to_see[0], queue[0]);
+        assert_eq!(mg.to_see[1].feed_url, addr);
+    }
+
+    #[tokio::test]
+    async fn test_vote_takes_the_shown_entry_after_a_sync() {
+        let mut _m = mockito::Server::new_async().await;
+        _m.mock("GET", "/")
+            .with_body(_rss_with_items(&["a"]))
+            .create_async()
+            .await;
+        let addr = format!("http://{}", _m.host_with_port());
+        let mut mg = FeedManager::new();
+        mg.new_feed(&addr).await.unwrap();
+        let shown = mg.to_see.last().unwrap().clone();
+
+        _m.reset();
+        _m.mock("GET", "/")
+            .with_body(_rss_with_items(&["b", "a"]))
+            .create_async()
+            .await;
+        mg.sync().await;
+
+        assert_eq!(mg.take_queued(&addr, "a"), Some(shown));
+        assert_eq!(mg.take_queued(&addr, "a"), None);
+        let ids: Vec<&str> = mg.to_see.iter().map(|e| e.entry.id.as_str()).collect();
+        assert_eq!(ids, ["b"]);
+    }
+
+    #[tokio::test]
+    async fn test_sync_drops_removed_entries_after_retention() {
+        let mut _m = mockito::Server::new_async().await;
+        _m.mock("GET", "/")
+            .with_body(_rss_with_items(&["a", "b"]))
+            .create_async()
+            .await;
+        let addr = format!("http://{}", _m.host_with_port());
+        let mut mg = FeedManager::new();
+        mg.new_feed(&addr).await.unwrap();
+
+        _m.reset();
+        _m.mock("GET", "/")
+            .with_body(_rss_with_items(&["b"]))
+            .create_async()
+            .await;
+        mg.sync().await;
+        let missing = mg.to_see.iter().find(|e| e.entry.id == "a").unwrap();
+        assert!(missing.missing_since.is_some());
+
+        mg.retention = Duration::ZERO;
+        mg.sync().await;
+        let ids: Vec<&str> = mg.to_see.iter().map(|e| e.entry.id.as_str()).collect();
+        assert_eq!(ids, ["b"]);
+
+        mg.filter_last(0.2);
+        _m.reset();
+        _m.mock("GET", "/")
+            .with_body(_rss_with_items(&[]))
+            .create_async()
+            .await;
+        mg.sync().await;
+        assert!(mg.filtered.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_remove_feed_drops_its_entries() {
+        let (_mock, mut mg) = _build_from_mock().await;
+        let addr = format!("http://{}", _mock.host_with_port());
+
+        mg.remove_feed_by_url(&addr);
+        assert!(mg.to_see.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_filtered_entries_are_not_queued_again() {
+        let mut _m = mockito::Server::new_async().await;
+        _m.mock("GET", "/")
+            .with_body(_rss_with_items(&["a", "b"]))
+            .create_async()
+            .await;
+        let addr = format!("http://{}", _m.host_with_port());
+        let mut mg = FeedManager::new();
+        mg.new_feed(&addr).await.unwrap();
+
+        mg.filter_last(0.2);
+        assert_eq!(mg.filtered.len(), 1);
+        assert_eq!(mg.filtered[0].0.entry.id, "b");
+        assert_eq!(mg.filtered[0].1, 0.2);
+
+        mg.sync().await;
+        let ids: Vec<&str> = mg.to_see.iter().map(|e| e.entry.id.as_str()).collect();
+        assert_eq!(ids, ["a"]);
+
+        assert!(mg.take_filtered(&addr, "a").is_none());
+        let rescued = mg.take_filtered(&addr, "b").unwrap();
+        assert_eq!(rescued.entry.id, "b");
+        assert!(mg.filtered.is_empty());
+    }
+
+    pub fn _rss_with_items(ids: &[&str]) -> String {
+        let items: String = ids
+            .iter()
+            .map(|id| {
+                format!(
+                    "<item><guid>{id}</guid><title>Item {id}</title>\
+                     <link>https://example.com/{id}</link></item>"
+                )
+            })
+            .collect();
+        format!("<rss version=\"2.0\"><channel><title>Mock</title>{items}</channel></rss>")
+    }
+
     pub async fn _build_from_mock() -> (mockito::ServerGuard, FeedManager) {
         let mut _m = mockito::Server::new_async().await;
         _m.mock("GET", "/")
@@ -131,28 +807,5 @@ mod tests {
         (_m, mg)
     }
 
-    const _RSS: &'static str = include_str!("../mocks/rss.xml");
-}
-
-fn default_feed() -> Feed {
-    Feed {
-        id: "".to_string(),
-        title: None,
-        updated: None,
-        authors: vec![],
-        links: vec![],
-        categories: vec![],
-        contributors: vec![],
-        generator: None,
-        icon: None,
-        logo: None,
-        rights: None,
-        entries: vec![],
-        language: None,
-        feed_type: feed_rs::model::FeedType::Atom,
-        description: None,
-        published: None,
-        rating: None,
-        ttl: None,
-    }
+    const _RSS: &str = include_str!("../mocks/rss.xml");
 }
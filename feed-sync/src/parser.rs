@@ -1,11 +1,59 @@
 use feed_rs::model::Entry;
 
 pub trait Parser {
-    fn into_html(&self) -> String;
+    #[allow(clippy::wrong_self_convention)]
+    fn into_html(&self) -> String {
+        self.into_html_with_reasons(None)
+    }
+
+    /// Like `into_html`, followed by a "why am I seeing this" section when
+    /// `reasons` are given.
+    #[allow(clippy::wrong_self_convention)]
+    fn into_html_with_reasons(&self, reasons: Option<&Reasons>) -> String;
+}
+
+/// Why an entry is shown: its score and the tokens that
+/// weighed the most each way, with their contribution to the log-odds.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Reasons {
+    pub score: f64,
+    pub toward_like: Vec<(String, f64)>,
+    pub toward_dislike: Vec<(String, f64)>,
+    /// Shown although the score is below the threshold, to learn whether
+    /// the classifier was right to hide it.
+    pub exploratory: bool,
+}
+
+impl Reasons {
+    fn to_html(&self) -> String {
+        let list = |tokens: &[(String, f64)]| {
+            tokens
+                .iter()
+                .map(|(token, contribution)| {
+                    format!("<li>{} ({:+.2})</li>", escape(token), contribution)
+                })
+                .collect::<String>()
+        };
+        format!(
+            "<details class=\"feed-item-why\"><summary>Why am I seeing this?</summary>\
+             <p>Scored {:.0}% likely to be liked.</p>\
+             <p>Toward like:</p><ul>{}</ul>\
+             <p>Toward dislike:</p><ul>{}</ul></details>",
+            self.score * 100.,
+            list(&self.toward_like),
+            list(&self.toward_dislike)
+        )
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
 }
 
 impl Parser for Entry {
-    fn into_html(&self) -> String {
+    fn into_html_with_reasons(&self, reasons: Option<&Reasons>) -> String {
         let mut html = String::new();
         let summary = self.summary.clone().unwrap_or_default().content;
         let mut content = self
@@ -17,6 +65,12 @@ impl Parser for Entry {
         if content == summary {
             content = "".to_string();
         }
+        if reasons.is_some_and(|reasons| reasons.exploratory) {
+            html.push_str(
+                "<p class=\"feed-item-exploratory\">Exploring: this would normally be \
+                 hidden, your vote tells the classifier whether it should be.</p>",
+            );
+        }
         html.push_str(&format!(
             "<h1 class=\"feed-item-title\">{}</h1>",
             self.title.clone().unwrap_or_default().content
@@ -45,12 +99,51 @@ impl Parser for Entry {
         ));
 
         html.push_str(&format!("<p class=\"feed-item-content\">{}</p>", content));
-        if let Some(link) = self.links.get(0) {
+        if let Some(link) = self.links.first() {
             html.push_str(&format!(
                 "<a  class=\"feed-item-link\" href='{}'>Read more</a>",
                 link.href
             ));
         }
+        if let Some(reasons) = reasons {
+            html.push_str(&reasons.to_html());
+        }
         html
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reasons_are_only_added_when_given() {
+        let entry = Entry::default();
+        let reasons = Reasons {
+            score: 0.87,
+            toward_like: vec![("rust".to_string(), 1.5)],
+            toward_dislike: vec![("<script>".to_string(), -0.25)],
+            exploratory: false,
+        };
+
+        assert!(!entry.into_html().contains("feed-item-why"));
+        let html = entry.into_html_with_reasons(Some(&reasons));
+        assert!(html.ends_with("</details>"));
+        assert!(html.contains("Scored 87% likely"));
+        assert!(html.contains("<li>rust (+1.50)</li>"));
+        assert!(html.contains("<li>&lt;script&gt; (-0.25)</li>"));
+        assert!(!html.contains("feed-item-exploratory"));
+    }
+
+    #[test]
+    fn test_exploratory_entries_are_marked() {
+        let reasons = Reasons {
+            score: 0.3,
+            exploratory: true,
+            ..Default::default()
+        };
+
+        let html = Entry::default().into_html_with_reasons(Some(&reasons));
+        assert!(html.starts_with("<p class=\"feed-item-exploratory\">"));
+    }
+}
@@ -1,156 +1,195 @@
-use feed_rs::model::{Content, Link};
-use rusqlite::{params, Connection};
+use feed_rs::model::Entry;
+use rusqlite::params;
+
+use std::collections::HashMap;
+
+use crate::schema::open;
+use crate::scoring::Score;
+use crate::{default_feed, FeedManager, FeedMeta, QueuedEntry};
+
+/// Votes are only ever appended, even a new vote on an entry voted on
+/// before, so the rowids a model learned keep meaning the same votes.
+const INSERT_VOTE: &str = "
+  INSERT INTO already_seen (
+      id, title, authors, content, links, summary,
+      categories, language, is_liked, published, updated, feed_url, media
+  ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)
+";
+
+/// [`INSERT_VOTE`] for entries not voted on yet.
+const INSERT_FIRST_VOTE: &str = "
+  INSERT INTO already_seen (
+      id, title, authors, content, links, summary,
+      categories, language, is_liked, published, updated, feed_url, media
+  ) SELECT ?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13
+  WHERE NOT EXISTS (SELECT 1 FROM already_seen WHERE feed_url IS ?12 AND id = ?1)
+";
+
+/// Changes to unread entries made in memory since the last
+/// [`FeedManager::save_entries`], by key, so a save only writes those.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Unsaved(HashMap<(String, String), Change>);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Change {
+    /// Only the scores are new.
+    Score,
+    /// Queued, or changed beyond its scores.
+    Row,
+    /// Dropped from the queue without a vote.
+    Removed,
+}
+
+impl Unsaved {
+    pub(crate) fn row(&mut self, queued: &QueuedEntry) {
+        self.0.insert(owned_key(queued), Change::Row);
+    }
+
+    pub(crate) fn score(&mut self, queued: &QueuedEntry) {
+        self.0.entry(owned_key(queued)).or_insert(Change::Score);
+    }
 
-use crate::{default_feed, FeedManager};
+    pub(crate) fn removed(&mut self, queued: &QueuedEntry) {
+        self.0.insert(owned_key(queued), Change::Removed);
+    }
+}
+
+fn owned_key(queued: &QueuedEntry) -> (String, String) {
+    (queued.feed_url.clone(), queued.entry.id.clone())
+}
+
+/// Stores the entry as it was fetched. Authors, links, categories and media
+/// keep every field feed-rs parsed, serialized as JSON arrays, and nothing is
+/// lowercased: normalizing is left to whoever reads the history.
+fn execute_already_seen_entry(
+    stmt: &mut rusqlite::Statement,
+    queued: &QueuedEntry,
+    is_liked: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let entry = &queued.entry;
+    let content = entry
+        .content
+        .as_ref()
+        .and_then(|c| c.body.as_deref())
+        .unwrap_or_default();
+
+    stmt.execute(params![
+        entry.id,
+        entry.title.as_ref().map_or("", |t| t.content.as_str()),
+        serde_json::to_string(&entry.authors)?,
+        content,
+        serde_json::to_string(&entry.links)?,
+        entry.summary.as_ref().map_or("", |s| s.content.as_str()),
+        serde_json::to_string(&entry.categories)?,
+        entry.language.as_deref().unwrap_or_default(),
+        if is_liked { 1 } else { 0 },
+        entry.published.map(|d| d.to_rfc3339()),
+        entry.updated.map(|d| d.to_rfc3339()),
+        queued.feed_url,
+        serde_json::to_string(&entry.media)?
+    ])?;
+    Ok(())
+}
 
 impl FeedManager {
     // persistence
+    /// Stores the judged entries that have no vote in the db yet.
     pub fn save_already_seen(&self, db_path: &str) -> Result<(), Box<dyn std::error::Error>> {
-        let conn = Connection::open(db_path)?;
+        let conn = open(db_path)?;
 
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS already_seen (
-              id TEXT PRIMARY KEY,
-              title TEXT,
-              authors TEXT,
-              content TEXT,
-              links TEXT,
-              summary TEXT,
-              categories TEXT,
-              language TEXT,
-              is_liked INTEGER
-          )",
-            [],
-        )?;
+        let mut stmt = conn.prepare(INSERT_FIRST_VOTE)?;
 
-        let mut stmt = conn.prepare(
+        Self::execute_each_entry_in_already_seen(self, &mut stmt)
+    }
+
+    /// Writes a single vote as soon as it is cast, marking the entry as read
+    /// and adding it to the training data in one transaction. Returns the
+    /// rowid of the training row.
+    pub fn save_vote(
+        &self,
+        db_path: &str,
+        queued: &QueuedEntry,
+        is_liked: bool,
+    ) -> Result<i64, Box<dyn std::error::Error>> {
+        let mut conn = open(db_path)?;
+
+        let tx = conn.transaction()?;
+        execute_already_seen_entry(&mut tx.prepare(INSERT_VOTE)?, queued, is_liked)?;
+        let rowid = tx.last_insert_rowid();
+        tx.execute(
             "
-          INSERT OR REPLACE INTO already_seen (
-              id, title, authors, content, links, summary,
-              categories, language, is_liked
-          ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)
+          INSERT INTO entries (
+              feed_url, id, entry, fetched_at, missing_since, is_read, is_liked
+          ) VALUES (?1, ?2, ?3, ?4, ?5, 1, ?6)
+          ON CONFLICT (feed_url, id) DO UPDATE SET is_read = 1, is_liked = excluded.is_liked
       ",
+            params![
+                queued.feed_url,
+                queued.entry.id,
+                serde_json::to_string(&queued.entry)?,
+                queued.fetched_at,
+                queued.missing_since,
+                is_liked
+            ],
         )?;
-
-        Self::execute_each_entry_in_already_seen(self, &mut stmt)
+        tx.commit()?;
+        Ok(rowid)
     }
 
     fn execute_each_entry_in_already_seen(
         &self,
         stmt: &mut rusqlite::Statement,
     ) -> Result<(), Box<dyn std::error::Error>> {
-        for (entry, is_liked) in &self.already_seen {
-            let authors_json = serde_json::to_string(
-                &entry
-                    .authors
-                    .iter()
-                    .map(|a| a.name.as_str().to_lowercase())
-                    .collect::<Vec<_>>(),
-            )?;
-            let content_json = serde_json::to_string(
-                &entry
-                    .content
-                    .clone()
-                    .unwrap_or(Content::default())
-                    .body
-                    .unwrap_or_default()
-                    .to_lowercase(),
-            )?;
-            let links_json = serde_json::to_string(
-                &entry
-                    .links
-                    .get(0)
-                    .unwrap_or(&Link {
-                        href: String::new(),
-                        rel: None,
-                        media_type: None,
-                        href_lang: None,
-                        title: None,
-                        length: None,
-                    })
-                    .href
-                    .to_lowercase(),
-            )?;
-            let categories_json = serde_json::to_string(
-                &entry
-                    .categories
-                    .iter()
-                    .map(|c| c.term.as_str().to_lowercase())
-                    .collect::<Vec<_>>(),
-            )?;
-            let language = entry
-                .language
-                .as_ref()
-                .map_or("", String::as_str)
-                .to_lowercase();
-
-            let title = entry
-                .title
-                .as_ref()
-                .map_or("", |t| t.content.as_str())
-                .to_lowercase();
-
-            stmt.execute(params![
-                entry.id.to_lowercase(),
-                title,
-                authors_json,
-                content_json,
-                links_json,
-                entry
-                    .summary
-                    .clone()
-                    .unwrap_or_default()
-                    .content
-                    .to_lowercase(),
-                categories_json,
-                language,
-                if *is_liked { 1 } else { 0 }
-            ])?;
+        for (queued, is_liked) in &self.already_seen {
+            execute_already_seen_entry(stmt, queued, *is_liked)?;
         }
         Ok(())
     }
 
     pub fn load_feeds_from_db(&mut self, db_path: &str) -> Result<(), Box<dyn std::error::Error>> {
-        let conn = Connection::open(db_path)?;
-
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS feeds (
-              id TEXT PRIMARY KEY,
-              url TEXT
-          )",
-            [],
-        )?;
-        let mut stmt = conn.prepare("SELECT url FROM feeds")?;
-        let feeds = stmt.query_map([], |row| Ok(row.get(0)?))?;
+        let conn = open(db_path)?;
+
+        let mut stmt =
+            conn.prepare("SELECT url, etag, last_modified, fetched_at, sync_interval FROM feeds")?;
+        let feeds = stmt.query_map([], |row| {
+            let meta = FeedMeta {
+                etag: row.get(1)?,
+                last_modified: row.get(2)?,
+                fetched_at: row.get(3)?,
+                sync_interval: row.get(4)?,
+            };
+            Ok((row.get::<_, String>(0)?, meta))
+        })?;
         for feed in feeds {
-            let url: String = feed?;
+            let (url, meta) = feed?;
+            self.feed_meta.insert(url.clone(), meta);
             self.add_feed(default_feed(), url)
         }
         Ok(())
     }
 
     pub fn save_feeds(&self, db_path: &str) -> Result<(), Box<dyn std::error::Error>> {
-        let conn = Connection::open(db_path)?;
-
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS feeds (
-              id TEXT PRIMARY KEY,
-              url TEXT
-          )",
-            [],
-        )?;
+        let conn = open(db_path)?;
 
         let mut stmt = conn.prepare(
             "
           INSERT OR REPLACE INTO feeds (
-              id, url
-          ) VALUES (?1, ?2)
+              id, url, etag, last_modified, fetched_at, sync_interval
+          ) VALUES (?1, ?2, ?3, ?4, ?5, ?6)
       ",
         )?;
 
         for (feed, url) in &self.feeds {
             let id = feed.id.clone();
-            stmt.execute(params![id, url])?;
+            let meta = self.feed_meta.get(url).cloned().unwrap_or_default();
+            stmt.execute(params![
+                id,
+                url,
+                meta.etag,
+                meta.last_modified,
+                meta.fetched_at,
+                meta.sync_interval
+            ])?;
         }
         Ok(())
     }
@@ -160,40 +199,205 @@ impl FeedManager {
         db_path: &str,
         url: &str,
     ) -> Result<(), Box<dyn std::error::Error>> {
-        let conn = Connection::open(db_path)?;
-
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS feeds (
-              id TEXT PRIMARY KEY,
-              url TEXT
-          )",
-            [],
-        )?;
+        let conn = open(db_path)?;
 
         let mut stmt = conn.prepare("DELETE FROM feeds WHERE url = ?1")?;
         stmt.execute(params![url])?;
+        conn.execute(
+            "DELETE FROM entries WHERE feed_url = ?1 AND is_read = 0",
+            params![url],
+        )?;
         self.remove_feed_by_url(url);
         Ok(())
     }
+
+    /// Writes what changed in the unread queue and the filtered entries
+    /// since the last save: entries queued, rescored or dropped. Votes are
+    /// written as they are cast, by [`FeedManager::save_vote`]. Rows are kept
+    /// as the whole serialized `Entry` so the queue can be rebuilt exactly by
+    /// [`FeedManager::load_entries_from_db`].
+    pub fn save_entries(&mut self, db_path: &str) -> Result<(), Box<dyn std::error::Error>> {
+        if self.unsaved.0.is_empty() {
+            return Ok(());
+        }
+        let mut conn = open(db_path)?;
+
+        let unread = self
+            .to_see
+            .iter()
+            .map(|queued| (queued.key(), (queued, None)))
+            .chain(
+                self.filtered
+                    .iter()
+                    .map(|(queued, score)| (queued.key(), (queued, Some(*score)))),
+            )
+            .collect::<HashMap<_, _>>();
+
+        let tx = conn.transaction()?;
+        {
+            let mut insert = tx.prepare(
+                "
+              INSERT INTO entries (
+                  feed_url, id, entry, fetched_at, missing_since, is_read,
+                  filtered_score, score, model_version, seq
+              ) VALUES (?1, ?2, ?3, ?4, ?5, 0, ?6, ?7, ?8, ?9)
+              ON CONFLICT (feed_url, id) DO UPDATE SET
+                  entry = excluded.entry,
+                  missing_since = excluded.missing_since,
+                  filtered_score = COALESCE(excluded.filtered_score, filtered_score),
+                  score = excluded.score,
+                  model_version = excluded.model_version,
+                  seq = excluded.seq
+              WHERE is_read = 0
+          ",
+            )?;
+            let mut rescore = tx.prepare(
+                "
+              UPDATE entries SET
+                  filtered_score = COALESCE(?3, filtered_score),
+                  score = ?4,
+                  model_version = ?5
+              WHERE feed_url = ?1 AND id = ?2 AND is_read = 0
+          ",
+            )?;
+            let mut delete =
+                tx.prepare("DELETE FROM entries WHERE feed_url = ?1 AND id = ?2 AND is_read = 0")?;
+
+            for ((feed_url, id), change) in &self.unsaved.0 {
+                let found = unread.get(&(feed_url.as_str(), id.as_str()));
+                match (change, found) {
+                    (Change::Removed, _) => {
+                        delete.execute(params![feed_url, id])?;
+                    }
+                    (Change::Row, Some((queued, filtered_score))) => {
+                        insert.execute(params![
+                            feed_url,
+                            id,
+                            serde_json::to_string(&queued.entry)?,
+                            queued.fetched_at,
+                            queued.missing_since,
+                            filtered_score,
+                            queued.score.map(|s| s.value),
+                            queued.score.map(|s| s.model_version),
+                            queued.seq
+                        ])?;
+                    }
+                    (Change::Score, Some((queued, filtered_score))) => {
+                        rescore.execute(params![
+                            feed_url,
+                            id,
+                            filtered_score,
+                            queued.score.map(|s| s.value),
+                            queued.score.map(|s| s.model_version)
+                        ])?;
+                    }
+                    // voted on or removed with its feed since, already written
+                    (_, None) => {}
+                }
+            }
+        }
+        tx.commit()?;
+        self.unsaved = Unsaved::default();
+        Ok(())
+    }
+
+    /// Restores the unread queue, the filtered and the judged entries saved by
+    /// [`FeedManager::save_entries`], replacing the ones in memory.
+    pub fn load_entries_from_db(
+        &mut self,
+        db_path: &str,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let conn = open(db_path)?;
+
+        let mut stmt = conn.prepare(
+            "SELECT feed_url, entry, fetched_at, missing_since, is_read, is_liked,
+                 filtered_score, score, model_version, seq
+             FROM entries ORDER BY seq, rowid",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, i64>(2)?,
+                row.get::<_, Option<i64>>(3)?,
+                row.get::<_, bool>(4)?,
+                row.get::<_, Option<bool>>(5)?,
+                row.get::<_, Option<f64>>(6)?,
+                row.get::<_, Option<f64>>(7)?
+                    .zip(row.get::<_, Option<i64>>(8)?)
+                    .map(|(value, model_version)| Score {
+                        value,
+                        model_version,
+                    }),
+                row.get::<_, Option<i64>>(9)?.unwrap_or_default(),
+            ))
+        })?;
+
+        self.to_see.clear();
+        self.already_seen.clear();
+        self.filtered.clear();
+        self.unsaved = Unsaved::default();
+        for row in rows {
+            let (
+                feed_url,
+                entry,
+                fetched_at,
+                missing_since,
+                is_read,
+                is_liked,
+                filtered_score,
+                score,
+                seq,
+            ) = row?;
+            if let Some(score) = score {
+                self.model_version = self.model_version.max(score.model_version);
+            }
+            let mut entry = serde_json::from_str::<Entry>(&entry)?;
+            // entries stored before `QueuedEntry::new` recorded their feed
+            entry.source.get_or_insert_with(|| feed_url.clone());
+            let queued = QueuedEntry {
+                entry,
+                feed_url,
+                fetched_at,
+                missing_since,
+                score,
+                seq,
+            };
+            match (is_read, is_liked) {
+                (false, _) => match filtered_score {
+                    Some(score) => self.filtered.push((queued, score)),
+                    None => self.to_see.push(queued),
+                },
+                (true, Some(is_liked)) => self.already_seen.push((queued, is_liked)),
+                (true, None) => {}
+            }
+        }
+        Ok(())
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use feed_rs::model::Entry;
+    use rusqlite::Connection;
 
-    use crate::tests::_build_from_mock;
+    use crate::tests::{_build_from_mock, _rss_with_items};
+    use crate::QueuedEntry;
 
     use super::*;
 
     use std::path::Path;
+    use std::time::Duration;
 
     #[test]
     fn test_save_already_seen() {
-        let binding = random_db_path();
-        let db_path = binding.as_str();
+        let binding = TestDb::upgraded();
+        let db_path = binding.path();
         let mut manager = FeedManager::new();
-        manager.already_seen.push((Entry::default(), false));
-        let _ = manager
+        manager
+            .already_seen
+            .push((QueuedEntry::new(Entry::default(), ""), false));
+        manager
             .save_already_seen(db_path)
             .expect("Failed to save already seen entries to the database");
 
@@ -225,24 +429,57 @@ mod tests {
             assert_eq!(id, "");
             assert_eq!(title, "");
             assert_eq!(authors, "[]");
-            assert_eq!(content, "\"\"");
-            assert_eq!(links, "\"\"");
+            assert_eq!(content, "");
+            assert_eq!(links, "[]");
             assert_eq!(summary, "");
             assert_eq!(categories, "[]");
             assert_eq!(language, "");
             assert_eq!(is_liked, 0);
         }
-        std::fs::remove_file(db_path).expect("Failed to remove test file");
+    }
+
+    #[tokio::test]
+    async fn test_save_already_seen_keeps_raw_fields() {
+        let binding = TestDb::upgraded();
+        let db_path = binding.path();
+        let (_mock, mut manager) = _build_from_mock().await;
+        let voted = manager.to_see.pop().unwrap();
+        manager
+            .save_vote(db_path, &voted, true)
+            .expect("Failed to save vote to the database");
+
+        let conn = Connection::open(db_path).unwrap();
+        let (id, title, links, published, feed_url) = conn
+            .query_row(
+                "SELECT id, title, links, published, feed_url FROM already_seen",
+                [],
+                |row| {
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        row.get::<_, String>(1)?,
+                        row.get::<_, String>(2)?,
+                        row.get::<_, Option<String>>(3)?,
+                        row.get::<_, String>(4)?,
+                    ))
+                },
+            )
+            .unwrap();
+        let links: Vec<feed_rs::model::Link> = serde_json::from_str(&links).unwrap();
+
+        assert_eq!(id, voted.entry.id);
+        assert_eq!(title, "Item Title");
+        assert_eq!(links, voted.entry.links);
+        assert_eq!(published.as_deref(), Some("2022-01-01T12:00:00+00:00"));
+        assert_eq!(feed_url, voted.feed_url);
     }
 
     #[tokio::test]
     async fn test_save_feeds() {
-        let binding = random_db_path();
-        let db_path = binding.as_str();
-        std::fs::File::create(db_path).expect("Failed to create test file");
+        let binding = TestDb::upgraded();
+        let db_path = binding.path();
         let (_mock, manager) = _build_from_mock().await;
 
-        let _ = manager
+        manager
             .save_feeds(db_path)
             .expect("Failed to save feeds to the database");
 
@@ -252,31 +489,289 @@ mod tests {
 
         assert!(Path::new(db_path).exists());
         assert_eq!(manager.feeds, copy_manager.feeds);
-        std::fs::remove_file(db_path).expect("Failed to remove test file");
+    }
+
+    #[tokio::test]
+    async fn test_feed_meta_round_trip() {
+        let binding = TestDb::upgraded();
+        let db_path = binding.path();
+        let (_mock, mut manager) = _build_from_mock().await;
+        let url = _mock.url();
+        let meta = FeedMeta {
+            etag: Some("\"abc\"".to_string()),
+            last_modified: Some("Wed, 21 Oct 2015 07:28:00 GMT".to_string()),
+            fetched_at: Some(1_700_000_000),
+            sync_interval: Some(600),
+        };
+        manager.feed_meta.insert(url.clone(), meta.clone());
+
+        manager
+            .save_feeds(db_path)
+            .expect("Failed to save feeds to the database");
+        manager.save_entries(db_path).unwrap();
+        let mut copy_manager = FeedManager::new();
+        copy_manager.load_feeds_from_db(db_path).unwrap();
+
+        assert_eq!(copy_manager.feed_meta.get(&url), Some(&meta));
+        assert_eq!(copy_manager.sync_jobs(None)[0].meta, FeedMeta::default());
+        copy_manager.load_entries_from_db(db_path).unwrap();
+        assert_eq!(copy_manager.sync_jobs(None)[0].meta, meta);
+    }
+
+    #[tokio::test]
+    async fn test_not_modified_after_a_restart_keeps_the_queue() {
+        let binding = TestDb::upgraded();
+        let db_path = binding.path();
+        let mut _mock = mockito::Server::new_async().await;
+        _mock
+            .mock("GET", "/")
+            .with_header("etag", "\"v1\"")
+            .with_body(_rss_with_items(&["a", "b"]))
+            .create_async()
+            .await;
+        let mut manager = FeedManager::new();
+        manager.new_feed(&_mock.url()).await.unwrap();
+        manager.save_feeds(db_path).unwrap();
+        manager.save_entries(db_path).unwrap();
+
+        let mut copy_manager = FeedManager::new();
+        copy_manager.load_feeds_from_db(db_path).unwrap();
+        copy_manager.load_entries_from_db(db_path).unwrap();
+        _mock.reset();
+        _mock
+            .mock("GET", "/")
+            .match_header("if-none-match", "\"v1\"")
+            .with_status(304)
+            .create_async()
+            .await;
+        copy_manager.retention = Duration::ZERO;
+        copy_manager.sync().await;
+        copy_manager.sync().await;
+
+        assert_eq!(_ids(&copy_manager.to_see), ["a", "b"]);
+        assert!(copy_manager
+            .to_see
+            .iter()
+            .all(|queued| queued.missing_since.is_none()));
+    }
+
+    #[test]
+    fn test_feeds_table_gains_cache_columns() {
+        let binding = TestDb::empty();
+        let db_path = binding.path();
+        let conn = Connection::open(db_path).unwrap();
+        conn.execute("CREATE TABLE feeds (id TEXT PRIMARY KEY, url TEXT)", [])
+            .unwrap();
+        conn.execute("INSERT INTO feeds (id, url) VALUES ('a', 'http://a')", [])
+            .unwrap();
+        crate::schema::upgrade(db_path).unwrap();
+
+        let mut manager = FeedManager::new();
+        manager.load_feeds_from_db(db_path).unwrap();
+
+        assert_eq!(
+            manager.feed_meta.get("http://a"),
+            Some(&FeedMeta::default())
+        );
     }
 
     #[tokio::test]
     async fn test_purge_feed() {
-        let binding = random_db_path();
-        let db_path = binding.as_str();
-        std::fs::File::create(db_path).expect("Failed to create test file");
+        let binding = TestDb::upgraded();
+        let db_path = binding.path();
         let (_mock, mut manager) = _build_from_mock().await;
 
         let binding = _mock.url();
         let url = binding.as_str();
         let feed = manager.get_feed(url).unwrap().clone();
 
-        let _ = manager
+        manager
             .purge_feed(db_path, url)
             .expect("Failed to purge feed from the database");
 
         assert!(!manager.feeds.contains(&(feed, url.to_string())));
-        std::fs::remove_file(db_path).expect("Failed to remove test file");
     }
-    fn random_db_path() -> String {
-        (DB_FOLDER.to_owned() + &uuid::Uuid::new_v4().to_string())
-            .as_str()
-            .to_owned()
+    #[tokio::test]
+    async fn test_entries_round_trip() {
+        let binding = TestDb::upgraded();
+        let db_path = binding.path();
+        let (_mock, mut manager) = _build_from_mock().await;
+        let url = _mock.url();
+        let mut judged = QueuedEntry::new(Entry::default(), &url);
+        judged.entry.id = "judged".to_string();
+        manager.save_vote(db_path, &judged, true).unwrap();
+        manager.already_seen.push((judged, true));
+        let scored = manager.to_see[0].key();
+        let scored = (scored.0.to_string(), scored.1.to_string());
+        manager.model_version = 2;
+        manager.publish_scores(HashMap::from([(scored, 0.75)]));
+
+        manager
+            .save_entries(db_path)
+            .expect("Failed to save entries to the database");
+        let mut copy_manager = FeedManager::new();
+        copy_manager.load_entries_from_db(db_path).unwrap();
+
+        assert_eq!(copy_manager.to_see, manager.to_see);
+        assert_eq!(copy_manager.already_seen, manager.already_seen);
+        assert_eq!(copy_manager.model_version, 3);
+    }
+
+    #[tokio::test]
+    async fn test_ranked_queue_loads_in_queue_order() {
+        let binding = TestDb::upgraded();
+        let db_path = binding.path();
+        let (_mock, mut manager) = _mock_with_items(&["a", "b", "c"]).await;
+        let queue = manager.to_see.clone();
+        manager.to_see.reverse();
+        manager.save_entries(db_path).unwrap();
+
+        let mut copy_manager = FeedManager::new();
+        copy_manager.load_entries_from_db(db_path).unwrap();
+        assert_eq!(copy_manager.to_see, queue);
+    }
+
+    #[tokio::test]
+    async fn test_save_entries_writes_what_changed() {
+        let binding = TestDb::upgraded();
+        let db_path = binding.path();
+        let (mut _mock, mut manager) = _mock_with_items(&["a", "b"]).await;
+        manager.save_entries(db_path).unwrap();
+
+        _mock.reset();
+        _mock
+            .mock("GET", "/")
+            .with_body(_rss_with_items(&["b", "c"]))
+            .create_async()
+            .await;
+        manager.retention = Duration::ZERO;
+        manager.sync().await;
+        manager.filter_last(0.1);
+        manager.save_entries(db_path).unwrap();
+
+        let mut copy_manager = FeedManager::new();
+        copy_manager.load_entries_from_db(db_path).unwrap();
+        assert_eq!(copy_manager.to_see, manager.to_see);
+        assert_eq!(copy_manager.filtered, manager.filtered);
+        assert_eq!(_ids(&copy_manager.to_see), ["b"]);
+    }
+
+    #[tokio::test]
+    async fn test_save_vote_writes_through() {
+        let binding = TestDb::upgraded();
+        let db_path = binding.path();
+        let (_mock, mut manager) = _build_from_mock().await;
+        manager.save_entries(db_path).unwrap();
+
+        let voted = manager.to_see.pop().unwrap();
+        manager
+            .save_vote(db_path, &voted, true)
+            .expect("Failed to save vote to the database");
+
+        let conn = Connection::open(db_path).unwrap();
+        let journal_mode: String = conn
+            .query_row("PRAGMA journal_mode", [], |row| row.get(0))
+            .unwrap();
+        let is_liked: i64 = conn
+            .query_row(
+                "SELECT is_liked FROM already_seen WHERE id = ?1",
+                params![voted.entry.id],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(journal_mode, "wal");
+        assert_eq!(is_liked, 1);
+
+        let mut copy_manager = FeedManager::new();
+        copy_manager.load_entries_from_db(db_path).unwrap();
+        assert!(copy_manager.to_see.is_empty());
+        assert_eq!(copy_manager.already_seen, vec![(voted, true)]);
+    }
+
+    #[tokio::test]
+    async fn test_filtered_entries_round_trip() {
+        let binding = TestDb::upgraded();
+        let db_path = binding.path();
+        let (_mock, mut manager) = _build_from_mock().await;
+        manager.filter_last(0.25);
+        manager.save_entries(db_path).unwrap();
+
+        let mut copy_manager = FeedManager::new();
+        copy_manager.load_entries_from_db(db_path).unwrap();
+        assert_eq!(copy_manager.to_see, manager.to_see);
+        assert_eq!(copy_manager.filtered, manager.filtered);
+
+        let (feed_url, id) = {
+            let (hidden, _) = &manager.filtered[0];
+            (hidden.feed_url.clone(), hidden.entry.id.clone())
+        };
+        let rescued = manager.take_filtered(&feed_url, &id).unwrap();
+        manager.save_vote(db_path, &rescued, true).unwrap();
+        manager.already_seen.push((rescued, true));
+        manager.save_entries(db_path).unwrap();
+
+        copy_manager.load_entries_from_db(db_path).unwrap();
+        assert!(copy_manager.filtered.is_empty());
+        assert_eq!(copy_manager.already_seen, manager.already_seen);
+        let score: f64 = Connection::open(db_path)
+            .unwrap()
+            .query_row(
+                "SELECT filtered_score FROM entries WHERE id = ?1",
+                params![id],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(score, 0.25);
+    }
+
+    /// A manager whose only feed lists entries with `ids`.
+    async fn _mock_with_items(ids: &[&str]) -> (mockito::ServerGuard, FeedManager) {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("GET", "/")
+            .with_body(_rss_with_items(ids))
+            .create_async()
+            .await;
+        let mut manager = FeedManager::new();
+        manager.new_feed(&server.url()).await.unwrap();
+        (server, manager)
+    }
+
+    fn _ids(queue: &[QueuedEntry]) -> Vec<&str> {
+        queue
+            .iter()
+            .map(|queued| queued.entry.id.as_str())
+            .collect()
+    }
+
+    /// A test db in the temp dir, removed along with the files WAL mode keeps
+    /// next to it when dropped, so a failing test doesn't leave it behind.
+    struct TestDb(String);
+
+    impl TestDb {
+        /// A path nothing was created at yet.
+        fn empty() -> TestDb {
+            let name = format!("feed-sync-{}.db", uuid::Uuid::new_v4());
+            TestDb(std::env::temp_dir().join(name).display().to_string())
+        }
+
+        /// A new db, upgraded like the server does at startup.
+        fn upgraded() -> TestDb {
+            let db = TestDb::empty();
+            crate::schema::upgrade(db.path()).unwrap();
+            db
+        }
+
+        fn path(&self) -> &str {
+            &self.0
+        }
+    }
+
+    impl Drop for TestDb {
+        fn drop(&mut self) {
+            for suffix in ["", "-wal", "-shm"] {
+                let _ = std::fs::remove_file(format!("{}{}", self.0, suffix));
+            }
+        }
     }
-    const DB_FOLDER: &str = "../db/";
 }
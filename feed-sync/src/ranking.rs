@@ -0,0 +1,249 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::{unix_now, FeedManager, QueuedEntry};
+
+/// Age at which `Order::Decay` halves a score, unless another is given.
+pub const DEFAULT_HALF_LIFE: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// The order entries of the queue are shown in.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Order {
+    /// The order `sync` queued them in, see [`QueuedEntry::seq`].
+    #[default]
+    Queue,
+    /// Most likely to be liked first, by the classifier's probability even
+    /// before it is prepared to filter.
+    Score,
+    /// Newest first, by published date, else updated date, else fetch time.
+    Recency,
+    /// Highest score first, the score halving every `half_life` of age.
+    Decay { half_life: Duration },
+    /// One entry of each feed in turn, newest first within a feed, so a busy
+    /// feed can't drown out a quiet one.
+    RoundRobin,
+    /// Score closest to 0.5 first: the entries whose votes teach the
+    /// classifier the most.
+    Training,
+}
+
+impl FromStr for Order {
+    type Err = String;
+
+    /// Parses the names used in `/next?order=`, e.g. `round-robin`.
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "queue" => Ok(Order::Queue),
+            "score" => Ok(Order::Score),
+            "recency" => Ok(Order::Recency),
+            "decay" => Ok(Order::Decay {
+                half_life: DEFAULT_HALF_LIFE,
+            }),
+            "round-robin" => Ok(Order::RoundRobin),
+            "training" => Ok(Order::Training),
+            _ => Err(format!("unknown order {:?}", name)),
+        }
+    }
+}
+
+impl fmt::Display for Order {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Order::Queue => write!(f, "queue"),
+            Order::Score => write!(f, "score"),
+            Order::Recency => write!(f, "recency"),
+            Order::Decay { .. } => write!(f, "decay"),
+            Order::RoundRobin => write!(f, "round-robin"),
+            Order::Training => write!(f, "training"),
+        }
+    }
+}
+
+impl FeedManager {
+    /// Reorders the queue so its last entry, the one shown next, is the
+    /// first by `order`. `score` is only called by the orders that use it,
+    /// e.g. to look up [`QueuedEntry::score`]. The order the entries were
+    /// queued in is kept in [`QueuedEntry::seq`], so [`Order::Queue`] and
+    /// saving the queue don't depend on the last ranking.
+    pub fn rank(&mut self, order: Order, score: impl Fn(&QueuedEntry) -> f64) {
+        rank(&mut self.to_see, order, score, unix_now());
+    }
+}
+
+/// Sorts `queue` by `order`, best last. Ties keep their queue order.
+pub fn rank(
+    queue: &mut Vec<QueuedEntry>,
+    order: Order,
+    score: impl Fn(&QueuedEntry) -> f64,
+    now: i64,
+) {
+    match order {
+        Order::Queue => sort_by_key(queue, |queued| queued.seq as f64),
+        Order::Score => sort_by_key(queue, &score),
+        Order::Recency => sort_by_key(queue, |queued| timestamp(queued) as f64),
+        Order::Training => sort_by_key(queue, |queued| -(score(queued) - 0.5).abs()),
+        Order::Decay { half_life } => {
+            let half_life = half_life.as_secs_f64().max(1.);
+            sort_by_key(queue, |queued| {
+                let age = (now - timestamp(queued)).max(0) as f64;
+                score(queued) * 0.5f64.powf(age / half_life)
+            })
+        }
+        Order::RoundRobin => {
+            let mut by_feed = BTreeMap::<String, Vec<QueuedEntry>>::new();
+            for queued in queue.drain(..) {
+                by_feed
+                    .entry(queued.feed_url.clone())
+                    .or_default()
+                    .push(queued);
+            }
+            let mut feeds = by_feed
+                .into_values()
+                .map(|mut entries| {
+                    // newest last, so popping takes the newest
+                    sort_by_key(&mut entries, |queued| timestamp(queued) as f64);
+                    entries
+                })
+                .collect::<Vec<_>>();
+            let mut turns = Vec::new();
+            while feeds.iter().any(|entries| !entries.is_empty()) {
+                turns.extend(feeds.iter_mut().filter_map(|entries| entries.pop()));
+            }
+            turns.reverse();
+            *queue = turns;
+        }
+    }
+}
+
+/// Stable ascending sort on a key computed once per entry.
+fn sort_by_key(queue: &mut Vec<QueuedEntry>, key: impl Fn(&QueuedEntry) -> f64) {
+    let mut keyed = queue
+        .drain(..)
+        .map(|queued| (key(&queued), queued))
+        .collect::<Vec<_>>();
+    keyed.sort_by(|(a, _), (b, _)| a.total_cmp(b));
+    queue.extend(keyed.into_iter().map(|(_, queued)| queued));
+}
+
+/// Unix timestamp of when the entry was written, as well as we know it.
+fn timestamp(queued: &QueuedEntry) -> i64 {
+    queued
+        .entry
+        .published
+        .or(queued.entry.updated)
+        .map_or(queued.fetched_at, |date| date.timestamp())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use feed_rs::model::{Entry, Text};
+
+    const DAY: i64 = 24 * 60 * 60;
+
+    /// An entry of `feed` fetched at `fetched_at`, titled `title`, whose score
+    /// the tests read from the title's length.
+    fn _queued(feed: &str, title: &str, fetched_at: i64) -> QueuedEntry {
+        let mut queued = QueuedEntry::new(
+            Entry {
+                id: title.to_string(),
+                title: Some(Text {
+                    content: title.to_string(),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+            feed,
+        );
+        queued.fetched_at = fetched_at;
+        queued
+    }
+
+    fn _score(queued: &QueuedEntry) -> f64 {
+        queued.entry.title.as_ref().unwrap().content.len() as f64
+    }
+
+    /// Ids in the order they will be shown.
+    fn _shown(queue: &[QueuedEntry]) -> Vec<&str> {
+        queue.iter().rev().map(|q| q.entry.id.as_str()).collect()
+    }
+
+    #[test]
+    fn test_score_and_recency() {
+        let mut queue = vec![
+            _queued("a", "aaa", 3 * DAY),
+            _queued("a", "a", 2 * DAY),
+            _queued("a", "aa", DAY),
+        ];
+        for (seq, queued) in queue.iter_mut().enumerate() {
+            queued.seq = seq as i64;
+        }
+
+        rank(&mut queue, Order::Queue, _score, 0);
+        assert_eq!(_shown(&queue), ["aa", "a", "aaa"]);
+        rank(&mut queue, Order::Score, _score, 0);
+        assert_eq!(_shown(&queue), ["aaa", "aa", "a"]);
+        rank(&mut queue, Order::Recency, _score, 0);
+        assert_eq!(_shown(&queue), ["aaa", "a", "aa"]);
+        rank(&mut queue, Order::Queue, _score, 0);
+        assert_eq!(_shown(&queue), ["aa", "a", "aaa"]);
+    }
+
+    #[test]
+    fn test_training_shows_uncertain_entries_first() {
+        let mut queue = vec![
+            _queued("a", "a", 0),
+            _queued("a", "aaaaaa", 0),
+            _queued("a", "aaa", 0),
+        ];
+        let score = |queued: &QueuedEntry| _score(queued) / 10.;
+
+        rank(&mut queue, Order::Training, score, 0);
+        assert_eq!(_shown(&queue), ["aaaaaa", "aaa", "a"]);
+    }
+
+    #[test]
+    fn test_decay_lets_a_fresh_entry_beat_an_old_better_one() {
+        let mut queue = vec![_queued("a", "aaaa", 0), _queued("a", "aa", 2 * DAY)];
+        let order = Order::Decay {
+            half_life: Duration::from_secs(DAY as u64),
+        };
+
+        // 4 * 0.5^2 = 1 against 2 * 0.5^0 = 2
+        rank(&mut queue, order, _score, 2 * DAY);
+        assert_eq!(_shown(&queue), ["aa", "aaaa"]);
+        // 4 * 0.5^0.5 > 2 * 0.5^0
+        rank(&mut queue, order, _score, DAY / 2);
+        assert_eq!(_shown(&queue), ["aaaa", "aa"]);
+    }
+
+    #[test]
+    fn test_round_robin_takes_turns_between_feeds() {
+        let mut queue = vec![
+            _queued("busy", "b1", 1),
+            _queued("busy", "b2", 2),
+            _queued("busy", "b3", 3),
+            _queued("quiet", "q1", 1),
+        ];
+
+        rank(&mut queue, Order::RoundRobin, _score, 0);
+        assert_eq!(_shown(&queue), ["b3", "q1", "b2", "b1"]);
+    }
+
+    #[test]
+    fn test_order_names() {
+        for name in [
+            "queue",
+            "score",
+            "recency",
+            "decay",
+            "round-robin",
+            "training",
+        ] {
+            assert_eq!(name.parse::<Order>().unwrap().to_string(), name);
+        }
+        assert!("random".parse::<Order>().is_err());
+    }
+}
@@ -0,0 +1,183 @@
+use rand::Rng;
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::{FeedManager, SyncReport, SyncStatus};
+
+/// Feeds are never refreshed more often than this, whatever their `ttl` says.
+const MIN_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Decides when each feed is due for a refresh.
+///
+/// The base interval of a feed is its per-feed override, else its RSS `ttl`,
+/// else `default_interval`. Every consecutive failure doubles it up to
+/// `max_backoff`, and the result is spread by up to `jitter` (a fraction of
+/// the interval) so feeds added together don't stay in lockstep.
+#[derive(Debug, Clone)]
+pub struct Scheduler {
+    pub default_interval: Duration,
+    pub max_backoff: Duration,
+    pub jitter: f64,
+    schedule: HashMap<String, Slot>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Slot {
+    next_due: Instant,
+    failures: u32,
+}
+
+impl Scheduler {
+    pub fn new(default_interval: Duration) -> Self {
+        Scheduler {
+            default_interval,
+            max_backoff: Duration::from_secs(6 * 60 * 60),
+            jitter: 0.1,
+            schedule: HashMap::new(),
+        }
+    }
+
+    /// Urls that should be fetched now. Feeds the scheduler has never seen
+    /// an outcome for are due immediately.
+    pub fn due<'a>(&self, urls: impl IntoIterator<Item = &'a String>, now: Instant) -> Vec<String> {
+        urls.into_iter()
+            .filter(|url| self.schedule.get(*url).is_none_or(|s| s.next_due <= now))
+            .cloned()
+            .collect()
+    }
+
+    /// Schedules the next fetch of every feed in `report`.
+    pub fn record(&mut self, manager: &FeedManager, report: &SyncReport, now: Instant) {
+        for (url, status) in &report.outcomes {
+            let ttl = manager.get_feed(url).and_then(|f| f.ttl);
+            let sync_interval = manager.feed_meta.get(url).and_then(|m| m.sync_interval);
+            self.record_outcome(url, status, ttl, sync_interval, now);
+        }
+        self.schedule
+            .retain(|url, _| manager.feeds.iter().any(|(_, u)| u == url));
+    }
+
+    fn record_outcome(
+        &mut self,
+        url: &str,
+        status: &SyncStatus,
+        ttl: Option<u32>,
+        sync_interval: Option<u64>,
+        now: Instant,
+    ) {
+        let failures = match self.schedule.get(url) {
+            Some(slot) if !status.is_ok() => slot.failures + 1,
+            None if !status.is_ok() => 1,
+            _ => 0,
+        };
+        let delay = self.with_jitter(self.backoff(self.interval(ttl, sync_interval), failures));
+        self.schedule.insert(
+            url.to_string(),
+            Slot {
+                next_due: now + delay,
+                failures,
+            },
+        );
+    }
+
+    /// Base refresh interval, `ttl` being in minutes as RSS defines it.
+    pub fn interval(&self, ttl: Option<u32>, sync_interval: Option<u64>) -> Duration {
+        let interval = match (sync_interval, ttl.filter(|t| *t > 0)) {
+            (Some(secs), _) => Duration::from_secs(secs),
+            (None, Some(minutes)) => Duration::from_secs(u64::from(minutes) * 60),
+            (None, None) => self.default_interval,
+        };
+        interval.max(MIN_INTERVAL)
+    }
+
+    pub fn backoff(&self, interval: Duration, failures: u32) -> Duration {
+        if failures == 0 {
+            return interval;
+        }
+        let factor = 2u32.saturating_pow(failures.min(16));
+        interval
+            .saturating_mul(factor)
+            .min(self.max_backoff.max(interval))
+    }
+
+    fn with_jitter(&self, delay: Duration) -> Duration {
+        if self.jitter <= 0. {
+            return delay;
+        }
+        let spread = rand::thread_rng().gen_range(-self.jitter..=self.jitter);
+        delay.mul_f64(1. + spread)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scheduler() -> Scheduler {
+        let mut scheduler = Scheduler::new(Duration::from_secs(900));
+        scheduler.jitter = 0.;
+        scheduler
+    }
+
+    #[test]
+    fn test_interval_precedence() {
+        let scheduler = scheduler();
+
+        assert_eq!(scheduler.interval(None, None), Duration::from_secs(900));
+        assert_eq!(
+            scheduler.interval(Some(30), None),
+            Duration::from_secs(1800)
+        );
+        assert_eq!(
+            scheduler.interval(Some(30), Some(120)),
+            Duration::from_secs(120)
+        );
+        assert_eq!(scheduler.interval(Some(0), None), Duration::from_secs(900));
+        assert_eq!(scheduler.interval(None, Some(1)), MIN_INTERVAL);
+    }
+
+    #[test]
+    fn test_backoff_doubles_and_caps() {
+        let scheduler = scheduler();
+        let interval = Duration::from_secs(900);
+
+        assert_eq!(scheduler.backoff(interval, 0), interval);
+        assert_eq!(scheduler.backoff(interval, 1), interval * 2);
+        assert_eq!(scheduler.backoff(interval, 3), interval * 8);
+        assert_eq!(scheduler.backoff(interval, 40), scheduler.max_backoff);
+    }
+
+    #[test]
+    fn test_due_follows_outcomes() {
+        let mut scheduler = scheduler();
+        let url = "http://example.com/rss".to_string();
+        let urls = [url.clone()];
+        let now = Instant::now();
+
+        assert_eq!(scheduler.due(&urls, now), urls);
+
+        scheduler.record_outcome(&url, &SyncStatus::Timeout, None, None, now);
+        scheduler.record_outcome(&url, &SyncStatus::Timeout, None, None, now);
+        assert!(scheduler
+            .due(&urls, now + Duration::from_secs(3599))
+            .is_empty());
+        assert_eq!(scheduler.due(&urls, now + Duration::from_secs(3600)), urls);
+
+        scheduler.record_outcome(&url, &SyncStatus::Synced, None, None, now);
+        assert_eq!(scheduler.due(&urls, now + Duration::from_secs(900)), urls);
+    }
+
+    #[test]
+    fn test_jitter_stays_within_bounds() {
+        let mut scheduler = scheduler();
+        scheduler.jitter = 0.1;
+        let delay = Duration::from_secs(1000);
+
+        for _ in 0..100 {
+            let jittered = scheduler.with_jitter(delay);
+            assert!(jittered >= Duration::from_secs(900));
+            assert!(jittered <= Duration::from_secs(1100));
+        }
+    }
+}
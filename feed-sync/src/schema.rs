@@ -0,0 +1,507 @@
+//! Versioned schema of the FeedHistory.db database.
+//!
+//! Every change to the schema is a new function appended to [`MIGRATIONS`];
+//! the version of a database is the number of migrations it has gone
+//! through, stored in `schema_version`. Databases written before versioning
+//! existed have no such table and start from version 0.
+
+use rusqlite::{params, Connection, Transaction};
+use serde_json::{json, Value};
+
+type Migration = fn(&Transaction) -> rusqlite::Result<()>;
+
+const MIGRATIONS: &[Migration] = &[
+    create_initial_tables,
+    add_feed_cache_columns,
+    create_entries_table,
+    key_feeds_by_url,
+    store_raw_already_seen,
+    add_filtered_score,
+    add_entry_score,
+    add_already_seen_media,
+    add_entry_seq,
+    append_votes,
+];
+
+pub const LATEST_VERSION: i64 = MIGRATIONS.len() as i64;
+
+/// Opens `db_path`, which must already be at [`LATEST_VERSION`], see
+/// [`upgrade`].
+pub fn open(db_path: &str) -> Result<Connection, Box<dyn std::error::Error>> {
+    let conn = connect(db_path)?;
+    // a db never migrated has no `schema_version` table
+    let current = version(&conn).unwrap_or(0);
+    if current != LATEST_VERSION {
+        return Err(format!(
+            "database schema version {} is not the supported {}, it must be upgraded first",
+            current, LATEST_VERSION
+        )
+        .into());
+    }
+    Ok(conn)
+}
+
+/// Brings `db_path` to [`LATEST_VERSION`], creating it if needed. Meant to
+/// run once at startup, before anything [`open`]s it.
+pub fn upgrade(db_path: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let mut conn = connect(db_path)?;
+    migrate(&mut conn)
+}
+
+/// Opens `db_path` in WAL mode, so single writes like votes commit without
+/// rewriting the db and don't block readers.
+fn connect(db_path: &str) -> rusqlite::Result<Connection> {
+    let conn = Connection::open(db_path)?;
+    conn.pragma_update(None, "journal_mode", "WAL")?;
+    conn.pragma_update(None, "synchronous", "NORMAL")?;
+    conn.busy_timeout(std::time::Duration::from_secs(5))?;
+    Ok(conn)
+}
+
+/// Runs every migration the database hasn't gone through yet, each in its
+/// own transaction.
+pub fn migrate(conn: &mut Connection) -> Result<(), Box<dyn std::error::Error>> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS schema_version (
+          version INTEGER NOT NULL
+      )",
+        [],
+    )?;
+    let current = version(conn)?;
+    if current > LATEST_VERSION {
+        return Err(format!(
+            "database schema version {} is newer than the supported {}",
+            current, LATEST_VERSION
+        )
+        .into());
+    }
+
+    for (version, migration) in MIGRATIONS.iter().enumerate().skip(current as usize) {
+        let tx = conn.transaction()?;
+        migration(&tx)?;
+        tx.execute("DELETE FROM schema_version", [])?;
+        tx.execute(
+            "INSERT INTO schema_version (version) VALUES (?1)",
+            params![version as i64 + 1],
+        )?;
+        tx.commit()?;
+    }
+    Ok(())
+}
+
+pub fn version(conn: &Connection) -> rusqlite::Result<i64> {
+    conn.query_row(
+        "SELECT COALESCE(MAX(version), 0) FROM schema_version",
+        [],
+        |row| row.get(0),
+    )
+}
+
+fn create_initial_tables(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE IF NOT EXISTS already_seen (
+          id TEXT PRIMARY KEY,
+          title TEXT,
+          authors TEXT,
+          content TEXT,
+          links TEXT,
+          summary TEXT,
+          categories TEXT,
+          language TEXT,
+          is_liked INTEGER
+      );
+      CREATE TABLE IF NOT EXISTS feeds (
+          id TEXT PRIMARY KEY,
+          url TEXT
+      );",
+    )
+}
+
+fn add_feed_cache_columns(tx: &Transaction) -> rusqlite::Result<()> {
+    add_column(tx, "feeds", "etag", "TEXT")?;
+    add_column(tx, "feeds", "last_modified", "TEXT")?;
+    add_column(tx, "feeds", "fetched_at", "INTEGER")?;
+    add_column(tx, "feeds", "sync_interval", "INTEGER")
+}
+
+fn create_entries_table(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute(
+        "CREATE TABLE IF NOT EXISTS entries (
+          feed_url TEXT NOT NULL,
+          id TEXT NOT NULL,
+          entry TEXT NOT NULL,
+          fetched_at INTEGER NOT NULL,
+          missing_since INTEGER,
+          is_read INTEGER NOT NULL DEFAULT 0,
+          is_liked INTEGER,
+          PRIMARY KEY (feed_url, id)
+      )",
+        [],
+    )?;
+    Ok(())
+}
+
+/// Feeds used to be keyed by the feed's own id, which is empty until the
+/// first successful fetch, so feeds that never synced overwrote each other.
+fn key_feeds_by_url(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE feeds_by_url (
+          url TEXT PRIMARY KEY,
+          id TEXT,
+          etag TEXT,
+          last_modified TEXT,
+          fetched_at INTEGER,
+          sync_interval INTEGER
+      );
+      INSERT OR IGNORE INTO feeds_by_url (url, id, etag, last_modified, fetched_at, sync_interval)
+          SELECT url, id, etag, last_modified, fetched_at, sync_interval
+          FROM feeds WHERE url IS NOT NULL;
+      DROP TABLE feeds;
+      ALTER TABLE feeds_by_url RENAME TO feeds;",
+    )
+}
+
+/// `already_seen` used to hold lowercased text, with the content and the first
+/// link wrapped in JSON strings and authors and categories as arrays of names.
+/// Rows now keep what feed-rs parsed: this unwraps the old encodings into the
+/// new ones. The lowercasing can't be undone.
+fn store_raw_already_seen(tx: &Transaction) -> rusqlite::Result<()> {
+    add_column(tx, "already_seen", "published", "TEXT")?;
+    add_column(tx, "already_seen", "updated", "TEXT")?;
+    add_column(tx, "already_seen", "feed_url", "TEXT")?;
+
+    let rows = tx
+        .prepare("SELECT rowid, authors, content, links, categories FROM already_seen")?
+        .query_map([], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, Option<String>>(1)?.unwrap_or_default(),
+                row.get::<_, Option<String>>(2)?.unwrap_or_default(),
+                row.get::<_, Option<String>>(3)?.unwrap_or_default(),
+                row.get::<_, Option<String>>(4)?.unwrap_or_default(),
+            ))
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    let mut update = tx.prepare(
+        "UPDATE already_seen SET authors = ?2, content = ?3, links = ?4, categories = ?5
+         WHERE rowid = ?1",
+    )?;
+    for (rowid, authors, content, links, categories) in rows {
+        let content = match serde_json::from_str::<Value>(&content) {
+            Ok(Value::String(content)) => content,
+            _ => content,
+        };
+        let links = match serde_json::from_str::<Value>(&links) {
+            Ok(Value::String(href)) if href.is_empty() => json!([]),
+            Ok(Value::String(href)) => json!([{ "href": href }]),
+            Ok(links @ Value::Array(_)) => links,
+            _ => json!([]),
+        };
+        update.execute(params![
+            rowid,
+            names_to_objects(&authors, "name").to_string(),
+            content,
+            links.to_string(),
+            names_to_objects(&categories, "term").to_string()
+        ])?;
+    }
+    Ok(())
+}
+
+/// Unread entries the classifier hid keep the score that hid them, so they
+/// can be reviewed. `NULL` for entries that were queued normally.
+fn add_filtered_score(tx: &Transaction) -> rusqlite::Result<()> {
+    add_column(tx, "entries", "filtered_score", "REAL")
+}
+
+/// Queued entries are scored once, when fetched, and again only when the
+/// model changes, see [`crate::scoring`].
+fn add_entry_score(tx: &Transaction) -> rusqlite::Result<()> {
+    add_column(tx, "entries", "score", "REAL")?;
+    add_column(tx, "entries", "model_version", "INTEGER")
+}
+
+/// Media objects, enclosures among them, are kept with the votes too. Rows
+/// voted on before get theirs from `entries` when it still has the entry.
+fn add_already_seen_media(tx: &Transaction) -> rusqlite::Result<()> {
+    add_column(tx, "already_seen", "media", "TEXT")?;
+
+    let rows = tx
+        .prepare(
+            "SELECT already_seen.rowid, entries.entry FROM already_seen
+             JOIN entries USING (feed_url, id)",
+        )?
+        .query_map([], |row| {
+            Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    let mut update = tx.prepare("UPDATE already_seen SET media = ?2 WHERE rowid = ?1")?;
+    for (rowid, entry) in rows {
+        let media = match serde_json::from_str::<Value>(&entry) {
+            Ok(entry) => entry["media"].clone(),
+            Err(_) => continue,
+        };
+        if media.is_array() {
+            update.execute(params![rowid, media.to_string()])?;
+        }
+    }
+    Ok(())
+}
+
+/// `["a", "b"]` into `[{"<field>": "a"}, {"<field>": "b"}]`.
+fn names_to_objects(json: &str, field: &str) -> Value {
+    let values = match serde_json::from_str::<Value>(json) {
+        Ok(Value::Array(values)) => values,
+        _ => return json!([]),
+    };
+    values
+        .into_iter()
+        .map(|value| match value {
+            Value::String(name) => json!({ field: name }),
+            other => other,
+        })
+        .collect()
+}
+
+/// Unread entries keep their place in the order `sync` queued them in,
+/// however the queue is ranked, see [`crate::QueuedEntry::seq`]. Rows
+/// written before were kept in that order.
+fn add_entry_seq(tx: &Transaction) -> rusqlite::Result<()> {
+    add_column(tx, "entries", "seq", "INTEGER")?;
+    tx.execute("UPDATE entries SET seq = rowid", [])?;
+    Ok(())
+}
+
+/// `already_seen` was keyed by entry id, so voting on an entry again replaced
+/// its row and gave it a new rowid, and models that had learned the old vote
+/// learned the new one on top of it. Votes are now appended, each with a
+/// `vote_id` that keeps the rowid models saved up to.
+fn append_votes(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "ALTER TABLE already_seen RENAME TO keyed_already_seen;
+         CREATE TABLE already_seen (
+             id TEXT,
+             title TEXT,
+             authors TEXT,
+             content TEXT,
+             links TEXT,
+             summary TEXT,
+             categories TEXT,
+             language TEXT,
+             is_liked INTEGER,
+             published TEXT,
+             updated TEXT,
+             feed_url TEXT,
+             media TEXT,
+             vote_id INTEGER PRIMARY KEY
+         );
+         INSERT INTO already_seen (
+             vote_id, id, title, authors, content, links, summary, categories,
+             language, is_liked, published, updated, feed_url, media
+         )
+         SELECT rowid, id, title, authors, content, links, summary, categories,
+             language, is_liked, published, updated, feed_url, media
+         FROM keyed_already_seen;
+         DROP TABLE keyed_already_seen;
+         CREATE INDEX already_seen_by_entry ON already_seen (feed_url, id);",
+    )
+}
+
+/// `ALTER TABLE ADD COLUMN` that tolerates databases written by builds that
+/// already added the column before migrations existed.
+fn add_column(tx: &Transaction, table: &str, column: &str, ty: &str) -> rusqlite::Result<()> {
+    let exists = tx
+        .prepare("SELECT 1 FROM pragma_table_info(?1) WHERE name = ?2")?
+        .exists(params![table, column])?;
+    if !exists {
+        tx.execute(
+            &format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, ty),
+            [],
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const V0_FIXTURE: &str = include_str!("../mocks/v0.sql");
+
+    fn columns(conn: &Connection, table: &str) -> Vec<String> {
+        conn.prepare("SELECT name FROM pragma_table_info(?1)")
+            .unwrap()
+            .query_map(params![table], |row| row.get(0))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_fresh_database_is_latest() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrate(&mut conn).unwrap();
+
+        assert_eq!(version(&conn).unwrap(), LATEST_VERSION);
+        assert_eq!(
+            columns(&conn, "feeds"),
+            [
+                "url",
+                "id",
+                "etag",
+                "last_modified",
+                "fetched_at",
+                "sync_interval"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_v0_database_upgrades_in_place() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(V0_FIXTURE).unwrap();
+
+        migrate(&mut conn).unwrap();
+
+        assert_eq!(version(&conn).unwrap(), LATEST_VERSION);
+        assert!(columns(&conn, "feeds").contains(&"sync_interval".to_string()));
+        assert!(columns(&conn, "entries").contains(&"is_read".to_string()));
+        let urls: Vec<String> = conn
+            .prepare("SELECT url FROM feeds ORDER BY url")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+        assert_eq!(urls, ["https://a.example/rss", "https://b.example/rss"]);
+        let seen: i64 = conn
+            .query_row("SELECT COUNT(*) FROM already_seen", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(seen, 2);
+        let (authors, content, links, categories): (String, String, String, String) = conn
+            .query_row(
+                "SELECT authors, content, links, categories FROM already_seen
+                 WHERE id = 'https://a.example/1'",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+            )
+            .unwrap();
+        assert_eq!(authors, r#"[{"name":"jane"}]"#);
+        assert_eq!(content, "<p>release notes</p>");
+        assert_eq!(links, r#"[{"href":"https://a.example/1"}]"#);
+        assert_eq!(categories, r#"[{"term":"rust"}]"#);
+        let links: String = conn
+            .query_row(
+                "SELECT links FROM already_seen WHERE id = 'https://a.example/2'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(links, r#"[{"href":"https://a.example/2"}]"#);
+    }
+
+    #[test]
+    fn test_media_of_old_votes_is_backfilled() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrate(&mut conn).unwrap();
+        conn.execute_batch(
+            r#"INSERT INTO already_seen (id, feed_url) VALUES ('1', 'f'), ('2', 'f');
+               INSERT INTO entries (feed_url, id, entry, fetched_at)
+                   VALUES ('f', '1', '{"media": [{"content": []}]}', 0);"#,
+        )
+        .unwrap();
+
+        let tx = conn.transaction().unwrap();
+        add_already_seen_media(&tx).unwrap();
+        tx.commit().unwrap();
+
+        let media: Vec<Option<String>> = conn
+            .prepare("SELECT media FROM already_seen ORDER BY id")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+        assert_eq!(media, [Some(r#"[{"content":[]}]"#.to_string()), None]);
+    }
+
+    #[test]
+    fn test_votes_keep_their_rowid_and_can_repeat() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        let tx = conn.transaction().unwrap();
+        for migration in &MIGRATIONS[..MIGRATIONS.len() - 1] {
+            migration(&tx).unwrap();
+        }
+        tx.execute_batch(
+            "INSERT INTO already_seen (id, feed_url) VALUES ('a', 'f'), ('b', 'f');
+             INSERT OR REPLACE INTO already_seen (id, feed_url) VALUES ('a', 'f');",
+        )
+        .unwrap();
+        append_votes(&tx).unwrap();
+        tx.execute(
+            "INSERT INTO already_seen (id, feed_url) VALUES ('b', 'f')",
+            [],
+        )
+        .unwrap();
+        tx.commit().unwrap();
+
+        let votes: Vec<(i64, String)> = conn
+            .prepare("SELECT rowid, id FROM already_seen ORDER BY rowid")
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+        assert_eq!(
+            votes,
+            [
+                (2, "b".to_string()),
+                (3, "a".to_string()),
+                (4, "b".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_migrate_is_idempotent() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrate(&mut conn).unwrap();
+        migrate(&mut conn).unwrap();
+
+        let rows: i64 = conn
+            .query_row("SELECT COUNT(*) FROM schema_version", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(rows, 1);
+        assert_eq!(version(&conn).unwrap(), LATEST_VERSION);
+    }
+
+    #[test]
+    fn test_newer_database_is_rejected() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrate(&mut conn).unwrap();
+        conn.execute(
+            "UPDATE schema_version SET version = ?1",
+            params![LATEST_VERSION + 1],
+        )
+        .unwrap();
+
+        assert!(migrate(&mut conn).is_err());
+    }
+
+    #[test]
+    fn test_open_only_accepts_upgraded_databases() {
+        let db_path = std::env::temp_dir()
+            .join(format!("{}-schema.db", std::process::id()))
+            .to_string_lossy()
+            .into_owned();
+        assert!(open(&db_path).is_err());
+
+        upgrade(&db_path).unwrap();
+        assert_eq!(version(&open(&db_path).unwrap()).unwrap(), LATEST_VERSION);
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{}", db_path, suffix));
+        }
+    }
+}
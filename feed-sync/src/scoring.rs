@@ -0,0 +1,167 @@
+use feed_rs::model::Entry;
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+use crate::FeedManager;
+
+/// What a model thought of an entry, and which set of scores it came with,
+/// see [`FeedManager::model_version`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Score {
+    pub value: f64,
+    pub model_version: i64,
+}
+
+/// Scores entries as [`FeedManager::apply_sync`] queues them, so serving
+/// them later doesn't have to.
+pub trait Scorer: Send + Sync {
+    fn score(&self, entry: &Entry) -> f64;
+}
+
+/// The [`Scorer`] of a [`FeedManager`].
+#[derive(Clone)]
+pub struct ScoreHook(pub Arc<dyn Scorer>);
+
+impl fmt::Debug for ScoreHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ScoreHook")
+    }
+}
+
+impl FeedManager {
+    /// Every queued and filtered entry by key, to be scored again without
+    /// holding on to the manager.
+    pub fn scoring_jobs(&self) -> Vec<((String, String), Entry)> {
+        self.to_see
+            .iter()
+            .chain(self.filtered.iter().map(|(queued, _)| queued))
+            .map(|queued| {
+                let (feed_url, id) = queued.key();
+                ((feed_url.to_string(), id.to_string()), queued.entry.clone())
+            })
+            .collect()
+    }
+
+    /// Stores scores computed from [`FeedManager::scoring_jobs`] all at once,
+    /// as the next [`FeedManager::model_version`]. Entries that left the queue
+    /// meanwhile are skipped, those queued meanwhile keep the score they were
+    /// queued with. Filtered entries stay filtered, with their new score.
+    pub fn publish_scores(&mut self, scores: HashMap<(String, String), f64>) {
+        self.model_version += 1;
+        let model_version = self.model_version;
+        let filtered = self
+            .filtered
+            .iter_mut()
+            .map(|(queued, score)| (queued, Some(score)));
+        for (queued, filtered_score) in self
+            .to_see
+            .iter_mut()
+            .map(|queued| (queued, None))
+            .chain(filtered)
+        {
+            let key = (queued.feed_url.clone(), queued.entry.id.clone());
+            if let Some(&value) = scores.get(&key) {
+                queued.score = Some(Score {
+                    value,
+                    model_version,
+                });
+                self.unsaved.score(queued);
+                if let Some(score) = filtered_score {
+                    *score = value;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::_build_from_mock;
+
+    /// Scores every entry by the length of its id.
+    struct IdLength;
+
+    impl Scorer for IdLength {
+        fn score(&self, entry: &Entry) -> f64 {
+            entry.id.len() as f64
+        }
+    }
+
+    #[tokio::test]
+    async fn test_entries_are_scored_when_queued() {
+        let mut _m = mockito::Server::new_async().await;
+        _m.mock("GET", "/")
+            .with_body(crate::tests::_rss_with_items(&["a", "bb"]))
+            .create_async()
+            .await;
+        let addr = format!("http://{}", _m.host_with_port());
+        let mut mg = FeedManager::new();
+        mg.scorer = Some(ScoreHook(Arc::new(IdLength)));
+        mg.new_feed(&addr).await.unwrap();
+
+        let scores = mg
+            .to_see
+            .iter()
+            .map(|queued| queued.score.unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            scores,
+            [1., 2.].map(|value| Score {
+                value,
+                model_version: 0
+            })
+        );
+    }
+
+    /// Scores 1 the entries that know their feed, 0 the others.
+    struct KnowsFeed;
+
+    impl Scorer for KnowsFeed {
+        fn score(&self, entry: &Entry) -> f64 {
+            entry.source.is_some() as i64 as f64
+        }
+    }
+
+    #[tokio::test]
+    async fn test_entries_are_scored_with_their_feed() {
+        let mut _m = mockito::Server::new_async().await;
+        _m.mock("GET", "/")
+            .with_body(crate::tests::_rss_with_items(&["a"]))
+            .create_async()
+            .await;
+        let mut mg = FeedManager::new();
+        mg.scorer = Some(ScoreHook(Arc::new(KnowsFeed)));
+        mg.new_feed(&format!("http://{}", _m.host_with_port()))
+            .await
+            .unwrap();
+
+        assert_eq!(mg.to_see[0].score.unwrap().value, 1.);
+    }
+
+    #[tokio::test]
+    async fn test_published_scores_replace_older_ones() {
+        let (_mock, mut mg) = _build_from_mock().await;
+        mg.filter_last(0.);
+        let jobs = mg.scoring_jobs();
+        assert_eq!(jobs.len(), mg.to_see.len() + 1);
+
+        let mut scores = jobs
+            .into_iter()
+            .map(|(key, entry)| (key, IdLength.score(&entry)))
+            .collect::<HashMap<_, _>>();
+        scores.insert(("gone".to_string(), "gone".to_string()), 0.);
+        mg.publish_scores(scores);
+
+        assert_eq!(mg.model_version, 1);
+        let (filtered, filtered_score) = &mg.filtered[0];
+        assert_eq!(*filtered_score, filtered.entry.id.len() as f64);
+        for queued in mg.to_see.iter().chain([filtered]) {
+            let score = queued.score.unwrap();
+            assert_eq!(score.value, queued.entry.id.len() as f64);
+            assert_eq!(score.model_version, 1);
+        }
+    }
+}
@@ -0,0 +1,75 @@
+//! Time to score one entry against vocabularies of growing size, with the
+//! compiled model and with a scan of the whole vocabulary as `classify` used
+//! to do. Run with `cargo bench -p naive_classifier`.
+
+use feed_rs::model::{Content, Entry};
+use naive_classifier::{classifier::Classifier, EntryContent, NaiveBayesClassifier};
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const WORDS_PER_ENTRY: usize = 200;
+const RUNS: u32 = 50;
+
+fn entry(words: impl Iterator<Item = String>) -> Entry {
+    Entry {
+        content: Some(Content {
+            body: Some(words.collect::<Vec<_>>().join(" ")),
+            ..Default::default()
+        }),
+        ..Default::default()
+    }
+}
+
+/// Trains on entries of `WORDS_PER_ENTRY` words until the vocabulary has
+/// `vocabulary` tokens.
+fn classifier(vocabulary: usize) -> NaiveBayesClassifier {
+    let entries = (0..vocabulary.div_ceil(WORDS_PER_ENTRY))
+        .map(|i| {
+            let words = (0..WORDS_PER_ENTRY).map(move |w| format!("w{}", i * WORDS_PER_ENTRY + w));
+            EntryContent::from_entry(&entry(words), i % 3 == 0)
+        })
+        .collect();
+    let mut classifier = NaiveBayesClassifier::new_classifier(1.);
+    classifier.train(entries);
+    classifier.is_prepared = true;
+    classifier
+}
+
+/// The pre-compilation scoring: two logs per vocabulary token.
+fn scan_vocabulary(classifier: &NaiveBayesClassifier, entry: &Entry) -> f64 {
+    let tokens = EntryContent::from_entry(entry, false).features(classifier.tokenizer());
+    let liked = classifier.liked_entries_count as f64;
+    let disliked = classifier.disliked_entries_count as f64;
+    let alpha = classifier.alpha;
+    let mut log_odds = (liked / (liked + disliked)).ln() - (disliked / (liked + disliked)).ln();
+    for token in classifier.tokens.iter() {
+        let p_like = (classifier.token_liked_counts[token] as f64 + alpha) / (liked + 2. * alpha);
+        let p_dislike =
+            (classifier.token_disliked_counts[token] as f64 + alpha) / (disliked + 2. * alpha);
+        log_odds += if tokens.contains(token) {
+            p_like.ln() - p_dislike.ln()
+        } else {
+            (1. - p_like).ln() - (1. - p_dislike).ln()
+        };
+    }
+    1. / (1. + (-log_odds).exp())
+}
+
+fn time(mut f: impl FnMut() -> f64) -> Duration {
+    let start = Instant::now();
+    for _ in 0..RUNS {
+        black_box(f());
+    }
+    start.elapsed() / RUNS
+}
+
+fn main() {
+    let scored = entry((0..WORDS_PER_ENTRY).map(|w| format!("w{}", w * 7)));
+    println!("{:>10} {:>12} {:>12}", "vocabulary", "compiled", "scan");
+    for vocabulary in [1_000, 10_000, 100_000] {
+        let classifier = classifier(vocabulary);
+        let compiled = time(|| classifier.classify(scored.clone()));
+        let scan = time(|| scan_vocabulary(&classifier, &scored));
+        println!("{:>10} {:>12?} {:>12?}", vocabulary, compiled, scan);
+    }
+}
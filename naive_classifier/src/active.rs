@@ -0,0 +1,122 @@
+//! Training mode: votes on the entries the model is least sure about teach
+//! it the most, so this keeps track of how much each one moved it.
+
+use feed_rs::model::Entry;
+use serde::Serialize;
+
+use crate::classifier::Classifier;
+
+/// How sure a probability is: 0 at 0.5, 1 at 0 or 1.
+pub fn confidence(probability: f64) -> f64 {
+    (2. * probability - 1.).abs()
+}
+
+/// What a vote changed in the model's view of the entries waiting to be
+/// shown.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct Shift {
+    /// Mean absolute change of their probability.
+    pub moved: f64,
+    /// Mean [`confidence`] in them before the vote.
+    pub confidence_before: f64,
+    /// Mean [`confidence`] in them after the vote.
+    pub confidence_after: f64,
+}
+
+impl Shift {
+    /// Compares the probabilities of the same entries before and after a
+    /// vote. No entries make no shift.
+    pub fn between(before: &[f64], after: &[f64]) -> Shift {
+        let n = before.len().min(after.len());
+        if n == 0 {
+            return Shift::default();
+        }
+        let mean = |values: &mut dyn Iterator<Item = f64>| values.sum::<f64>() / n as f64;
+        Shift {
+            moved: mean(&mut before.iter().zip(after).map(|(b, a)| (a - b).abs())),
+            confidence_before: mean(&mut before.iter().take(n).map(|&p| confidence(p))),
+            confidence_after: mean(&mut after.iter().take(n).map(|&p| confidence(p))),
+        }
+    }
+}
+
+/// Teaches `classifier` a vote on `entry` and measures the [`Shift`] it
+/// caused on `sample`.
+pub fn update_measuring(
+    classifier: &mut dyn Classifier,
+    entry: &Entry,
+    liked: bool,
+    sample: &[&Entry],
+) -> Shift {
+    let before = sample
+        .iter()
+        .map(|entry| classifier.probability(entry))
+        .collect::<Vec<_>>();
+    classifier.update(entry, liked);
+    let after = sample
+        .iter()
+        .map(|entry| classifier.probability(entry))
+        .collect::<Vec<_>>();
+    Shift::between(&before, &after)
+}
+
+/// The shifts of the votes cast in training mode.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct TrainingLog {
+    pub votes: usize,
+    pub last: Option<Shift>,
+    /// Mean of `moved` over every vote.
+    pub mean_moved: f64,
+}
+
+impl TrainingLog {
+    pub fn record(&mut self, shift: Shift) {
+        self.votes += 1;
+        self.mean_moved += (shift.moved - self.mean_moved) / self.votes as f64;
+        self.last = Some(shift);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::classifier::ModelKind;
+    use crate::tests::_text_entry;
+    use crate::tokenizer::TokenizerConfig;
+
+    #[test]
+    fn test_shift_between_probabilities() {
+        let shift = Shift::between(&[0.5, 0.9], &[0.7, 0.8]);
+
+        assert!((shift.moved - 0.15).abs() < 1e-12);
+        assert!((shift.confidence_before - 0.4).abs() < 1e-12);
+        assert!((shift.confidence_after - 0.5).abs() < 1e-12);
+        assert_eq!(Shift::between(&[], &[]), Shift::default());
+    }
+
+    #[test]
+    fn test_votes_move_the_model() {
+        let mut model = ModelKind::NaiveBayes.build(TokenizerConfig::default().build().unwrap());
+        let rust = _text_entry("rust");
+        let crypto = _text_entry("crypto");
+        for _ in 0..3 {
+            model.update(&rust, true);
+            model.update(&crypto, false);
+        }
+        let shift = update_measuring(model.as_mut(), &rust, true, &[&rust]);
+        assert!(shift.moved > 0.);
+        assert!(shift.confidence_after > shift.confidence_before);
+        assert_eq!(
+            update_measuring(model.as_mut(), &crypto, false, &[]),
+            Shift::default()
+        );
+
+        let mut log = TrainingLog::default();
+        log.record(shift);
+        log.record(Shift::default());
+        assert_eq!(log.votes, 2);
+        assert!((log.mean_moved - shift.moved / 2.).abs() < 1e-12);
+        assert_eq!(log.last, Some(Shift::default()));
+        assert_eq!(model.votes_seen(), 8);
+    }
+}
@@ -0,0 +1,117 @@
+//! Cross-validates a model on the votes in FeedHistory.db.
+//!
+//!     cargo run -p naive_classifier --bin evaluate -- [options]
+//!
+//! --db <path>             votes to evaluate on (db/FeedHistory.db)
+//! --model <kind>          naive-bayes, logistic-regression,
+//!                         multinomial-naive-bayes or complement-naive-bayes
+//!                         (naive-bayes)
+//! --alpha <alpha>         smoothing of the Naive Bayes models (1.0)
+//! --weighting <kind>      counts, tf-idf or length-normalized, for the
+//!                         multinomial models (counts)
+//! --folds <k>             k-fold split (the default, with 5 folds)
+//! --time-split <fraction> train on the oldest votes, test on the newest fraction
+//! --stopwords, --stemming, --bigrams, --structure, --language <tag>
+//!                         tokenizer settings, as in the server config
+
+use naive_classifier::classifier::{Classifier, ModelKind};
+use naive_classifier::eval::{predict, Report, Split};
+use naive_classifier::multinomial::{MultinomialNaiveBayes, Weighting};
+use naive_classifier::tokenizer::TokenizerConfig;
+use naive_classifier::{votes_since, NaiveBayesClassifier};
+
+use std::process::exit;
+
+struct Options {
+    db_path: String,
+    model: ModelKind,
+    alpha: Option<f64>,
+    weighting: Weighting,
+    split: Split,
+    tokenizer: TokenizerConfig,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut options = Options {
+        db_path: "db/FeedHistory.db".to_string(),
+        model: ModelKind::NaiveBayes,
+        alpha: None,
+        weighting: Weighting::default(),
+        split: Split::KFold(5),
+        tokenizer: TokenizerConfig::default(),
+    };
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--db" => options.db_path = value()?,
+            "--model" => options.model = value()?.parse()?,
+            "--alpha" => {
+                options.alpha = Some(value()?.parse().map_err(|e| format!("--alpha: {}", e))?)
+            }
+            "--weighting" => options.weighting = value()?.parse()?,
+            "--folds" => {
+                let k = value()?.parse().map_err(|e| format!("--folds: {}", e))?;
+                if k < 2 {
+                    return Err("--folds needs at least 2 folds".to_string());
+                }
+                options.split = Split::KFold(k);
+            }
+            "--time-split" => {
+                let test_fraction: f64 = value()?
+                    .parse()
+                    .map_err(|e| format!("--time-split: {}", e))?;
+                if !(test_fraction > 0. && test_fraction < 1.) {
+                    return Err(
+                        "--time-split takes a fraction strictly between 0 and 1".to_string()
+                    );
+                }
+                options.split = Split::TimeOrdered { test_fraction };
+            }
+            "--stopwords" => options.tokenizer.stopwords = true,
+            "--stemming" => options.tokenizer.stemming = true,
+            "--bigrams" => options.tokenizer.bigrams = true,
+            "--structure" => options.tokenizer.structure = true,
+            "--language" => options.tokenizer.default_language = Some(value()?),
+            _ => return Err(format!("unknown option {}", arg)),
+        }
+    }
+    if options.model == ModelKind::LogisticRegression && options.alpha.is_some() {
+        return Err("--alpha only applies to the Naive Bayes models".to_string());
+    }
+    Ok(options)
+}
+
+fn main() {
+    let options = parse_args(std::env::args().skip(1)).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        exit(2);
+    });
+    let votes = votes_since(&options.db_path, 0)
+        .unwrap_or_else(|e| {
+            eprintln!("failed to read {}: {}", options.db_path, e);
+            exit(1);
+        })
+        .into_iter()
+        .map(|(_, vote)| vote)
+        .collect::<Vec<_>>();
+
+    let new_model = || -> Box<dyn Classifier> {
+        let tokenizer = options.tokenizer.build().unwrap();
+        let alpha = options.alpha.unwrap_or(1.0);
+        match options.model {
+            ModelKind::NaiveBayes => {
+                Box::new(NaiveBayesClassifier::with_tokenizer(alpha, tokenizer))
+            }
+            kind @ (ModelKind::MultinomialNaiveBayes | ModelKind::ComplementNaiveBayes) => {
+                let complement = kind == ModelKind::ComplementNaiveBayes;
+                let mut model =
+                    MultinomialNaiveBayes::new(complement, options.weighting, tokenizer);
+                model.alpha = alpha;
+                Box::new(model)
+            }
+            kind => kind.build_weighted(tokenizer, options.weighting),
+        }
+    };
+    let predictions = predict(&votes, options.split, new_model);
+    print!("{}", Report::new(&predictions));
+}
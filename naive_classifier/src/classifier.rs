@@ -0,0 +1,362 @@
+//! What the server needs from a model, so Naive Bayes can be swapped for
+//! another one without touching it.
+
+use feed_rs::model::Entry;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+use crate::logistic::LogisticRegression;
+use crate::multinomial::{MultinomialNaiveBayes, Weighting};
+use crate::tokenizer::Tokenizer;
+use crate::{EntryContent, NaiveBayesClassifier, MIN_TRAINING_ENTRIES};
+
+/// Version of the files written by [`Classifier::save`].
+pub const MODEL_FORMAT_VERSION: u32 = 5;
+
+pub trait Classifier: Send {
+    fn kind(&self) -> ModelKind;
+
+    /// Learns from `data`, in order.
+    fn train(&mut self, data: Vec<EntryContent>);
+
+    /// Learns from a single vote, as it is cast.
+    fn update(&mut self, entry: &Entry, liked: bool) {
+        self.train(vec![EntryContent::from_entry(entry, liked)]);
+    }
+
+    /// Whether the model has seen enough votes to be trusted with filtering.
+    fn is_prepared(&self) -> bool;
+
+    /// Votes learned so far.
+    fn votes_seen(&self) -> i32;
+
+    /// Votes still needed before [`Classifier::is_prepared`].
+    fn votes_to_prepare(&self) -> i32 {
+        if self.is_prepared() {
+            0
+        } else {
+            (MIN_TRAINING_ENTRIES + 1 - self.votes_seen()).max(0)
+        }
+    }
+
+    /// How the model weighs feature counts, for the models that count them.
+    fn weighting(&self) -> Option<Weighting> {
+        None
+    }
+
+    /// Probability, between 0 and 1, that the user likes `content`, however
+    /// few votes the model has seen.
+    fn score_content(&self, content: &EntryContent) -> f64;
+
+    /// Probability, between 0 and 1, that the user likes `entry`. Models
+    /// that haven't seen enough votes yet return 1 so nothing is filtered out.
+    fn score(&self, entry: &Entry) -> f64 {
+        if !self.is_prepared() {
+            return 1.;
+        }
+        self.probability(entry)
+    }
+
+    /// [`Classifier::score_content`] of `entry`: the model's best guess even
+    /// before it is prepared, e.g. for ranking.
+    fn probability(&self, entry: &Entry) -> f64 {
+        self.score_content(&EntryContent::from_entry(entry, false))
+    }
+
+    /// The features of `entry` the model knows, and how much each moved its
+    /// score.
+    fn explain(&self, entry: &Entry) -> Explanation;
+
+    fn save(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>>;
+
+    fn tokenizer(&self) -> &dyn Tokenizer;
+
+    /// Rowid of the last `already_seen` row learned from, so a reloaded
+    /// model only has to learn the votes cast after it was saved.
+    fn trained_up_to(&self) -> i64;
+
+    fn set_trained_up_to(&mut self, rowid: i64);
+
+    /// Learns the votes cast after [`Classifier::trained_up_to`], as read
+    /// by the caller with [`crate::votes_since`], and moves it past them.
+    fn fold_in(&mut self, votes: Vec<(i64, EntryContent)>) {
+        if let Some((last, _)) = votes.last() {
+            self.set_trained_up_to(*last);
+        }
+        self.train(votes.into_iter().map(|(_, vote)| vote).collect());
+    }
+}
+
+/// The models this build can train, chosen with the `model` rocket config
+/// key.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ModelKind {
+    /// Bernoulli: whether each feature occurs.
+    #[default]
+    NaiveBayes,
+    LogisticRegression,
+    /// How often each feature occurs.
+    MultinomialNaiveBayes,
+    /// Like `MultinomialNaiveBayes`, made for unbalanced votes.
+    ComplementNaiveBayes,
+}
+
+impl ModelKind {
+    pub const ALL: [ModelKind; 4] = [
+        ModelKind::NaiveBayes,
+        ModelKind::LogisticRegression,
+        ModelKind::MultinomialNaiveBayes,
+        ModelKind::ComplementNaiveBayes,
+    ];
+
+    /// An untrained model of this kind.
+    pub fn build(self, tokenizer: Box<dyn Tokenizer>) -> Box<dyn Classifier> {
+        self.build_weighted(tokenizer, Weighting::default())
+    }
+
+    /// An untrained model of this kind, `weighting` applying to the kinds
+    /// that count features.
+    pub fn build_weighted(
+        self,
+        tokenizer: Box<dyn Tokenizer>,
+        weighting: Weighting,
+    ) -> Box<dyn Classifier> {
+        match self {
+            ModelKind::NaiveBayes => Box::new(NaiveBayesClassifier::with_tokenizer(1.0, tokenizer)),
+            ModelKind::LogisticRegression => Box::new(LogisticRegression::new(tokenizer)),
+            ModelKind::MultinomialNaiveBayes => {
+                Box::new(MultinomialNaiveBayes::new(false, weighting, tokenizer))
+            }
+            ModelKind::ComplementNaiveBayes => {
+                Box::new(MultinomialNaiveBayes::new(true, weighting, tokenizer))
+            }
+        }
+    }
+}
+
+impl std::str::FromStr for ModelKind {
+    type Err = String;
+
+    /// Parses the names used in the config, e.g. `logistic-regression`.
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        serde_json::from_value(serde_json::Value::String(name.to_string()))
+            .map_err(|_| format!("unknown model {:?}", name))
+    }
+}
+
+/// A score broken down into log-odds: the score is the logistic function of
+/// `baseline` plus every contribution.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Explanation {
+    /// Log-odds of an entry that has none of the features the model knows.
+    pub baseline: f64,
+    /// Features of the entry with what each adds to the log-odds, largest
+    /// first in absolute value.
+    pub contributions: Vec<(String, f64)>,
+}
+
+impl Explanation {
+    pub(crate) fn new(baseline: f64, mut contributions: Vec<(String, f64)>) -> Self {
+        contributions.sort_by(|(a_token, a), (b_token, b)| {
+            b.abs()
+                .total_cmp(&a.abs())
+                .then_with(|| a_token.cmp(b_token))
+        });
+        Explanation {
+            baseline,
+            contributions,
+        }
+    }
+
+    /// Up to `n` features that raised the score, strongest first.
+    pub fn toward_like(&self, n: usize) -> Vec<(String, f64)> {
+        self.strongest(n, |c| c > 0.)
+    }
+
+    /// Up to `n` features that lowered the score, strongest first.
+    pub fn toward_dislike(&self, n: usize) -> Vec<(String, f64)> {
+        self.strongest(n, |c| c < 0.)
+    }
+
+    fn strongest(&self, n: usize, keep: impl Fn(f64) -> bool) -> Vec<(String, f64)> {
+        self.contributions
+            .iter()
+            .filter(|(_, c)| keep(*c))
+            .take(n)
+            .cloned()
+            .collect()
+    }
+
+    pub fn score(&self) -> f64 {
+        let log_odds = self.baseline + self.contributions.iter().map(|(_, c)| c).sum::<f64>();
+        1. / (1. + (-log_odds).exp())
+    }
+}
+
+/// Fields every saved model starts with.
+#[derive(Deserialize)]
+struct Header {
+    format_version: u32,
+    model: ModelKind,
+}
+
+/// Loads a model saved by [`Classifier::save`], whatever its kind.
+pub fn load(path: &Path) -> Result<Box<dyn Classifier>, Box<dyn std::error::Error>> {
+    let bytes = fs::read(path)?;
+    let header = read_header(&bytes)?;
+    Ok(match header.model {
+        ModelKind::NaiveBayes => Box::new(NaiveBayesClassifier::from_saved(&bytes)?),
+        ModelKind::LogisticRegression => Box::new(LogisticRegression::from_saved(&bytes)?),
+        kind @ (ModelKind::MultinomialNaiveBayes | ModelKind::ComplementNaiveBayes) => {
+            Box::new(MultinomialNaiveBayes::from_saved(&bytes, kind)?)
+        }
+    })
+}
+
+fn read_header(bytes: &[u8]) -> Result<Header, Box<dyn std::error::Error>> {
+    let header: Header = serde_json::from_slice(bytes)?;
+    if header.format_version != MODEL_FORMAT_VERSION {
+        return Err(format!(
+            "model format version {} is not the supported {}",
+            header.format_version, MODEL_FORMAT_VERSION
+        )
+        .into());
+    }
+    Ok(header)
+}
+
+/// Checks that `bytes` hold a current model of `kind`.
+pub(crate) fn check_saved(bytes: &[u8], kind: ModelKind) -> Result<(), Box<dyn std::error::Error>> {
+    let header = read_header(bytes)?;
+    if header.model != kind {
+        return Err(format!("model is a {:?}, not a {:?}", header.model, kind).into());
+    }
+    Ok(())
+}
+
+pub(crate) fn write_model(
+    path: &Path,
+    model: &impl Serialize,
+) -> Result<(), Box<dyn std::error::Error>> {
+    // Written aside then renamed, so a crash never leaves half a model.
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, serde_json::to_vec(model)?)?;
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::_text_entry;
+    use crate::tokenizer::TokenizerConfig;
+
+    #[test]
+    fn test_every_kind_round_trips_through_load() {
+        for kind in ModelKind::ALL {
+            let path = std::env::temp_dir().join(format!("{}-{:?}.json", std::process::id(), kind));
+            let mut model = kind.build(TokenizerConfig::default().build().unwrap());
+            for i in 0..120 {
+                let text = if i % 2 == 0 {
+                    "rust async"
+                } else {
+                    "crypto deals"
+                };
+                model.update(&_text_entry(text), i % 2 == 0);
+            }
+            model.set_trained_up_to(120);
+            model.save(&path).unwrap();
+            let loaded = load(&path).unwrap();
+            let _ = fs::remove_file(&path);
+
+            let entry = _text_entry("rust crypto");
+            assert_eq!(loaded.kind(), kind);
+            assert_eq!(loaded.trained_up_to(), 120);
+            assert!((loaded.score(&entry) - model.score(&entry)).abs() < 1e-12);
+            assert!(model.score(&_text_entry("rust")) > 0.5);
+            assert!(model.score(&_text_entry("crypto")) < 0.5);
+        }
+    }
+
+    #[test]
+    fn test_field_weighted_zero_is_ignored() {
+        let config = TokenizerConfig {
+            fields: crate::tokenizer::FieldWeights {
+                title: 0.,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let titled = |title: &str, text: &str| Entry {
+            title: Some(feed_rs::model::Text {
+                content: title.to_string(),
+                ..Default::default()
+            }),
+            .._text_entry(text)
+        };
+        for kind in ModelKind::ALL {
+            let mut model = kind.build(config.build().unwrap());
+            for _ in 0..10 {
+                model.update(&titled("rust", "async"), true);
+                model.update(&titled("crypto", "deals"), false);
+            }
+
+            let untitled = model.probability(&_text_entry("async deals"));
+            for title in ["rust", "crypto", "unknown"] {
+                let score = model.probability(&titled(title, "async deals"));
+                assert!((score - untitled).abs() < 1e-12, "{:?} {}", kind, title);
+            }
+        }
+    }
+
+    #[test]
+    fn test_explanation_splits_contributions_by_direction() {
+        let explanation = Explanation::new(
+            0.5,
+            vec![
+                ("a".to_string(), 0.1),
+                ("b".to_string(), -2.),
+                ("c".to_string(), 1.),
+                ("d".to_string(), -0.5),
+                ("e".to_string(), 3.),
+            ],
+        );
+
+        assert_eq!(
+            explanation.toward_like(2),
+            [("e".to_string(), 3.), ("c".to_string(), 1.)]
+        );
+        assert_eq!(
+            explanation.toward_dislike(5),
+            [("b".to_string(), -2.), ("d".to_string(), -0.5)]
+        );
+    }
+
+    #[test]
+    fn test_explanation_adds_up_to_the_score() {
+        for kind in ModelKind::ALL {
+            let mut model = kind.build(TokenizerConfig::default().build().unwrap());
+            for i in 0..120 {
+                let text = if i % 3 == 0 {
+                    "rust async"
+                } else {
+                    "crypto deals"
+                };
+                model.update(&_text_entry(text), i % 3 == 0);
+            }
+            let entry = _text_entry("rust deals unseen");
+            let explanation = model.explain(&entry);
+
+            assert!((explanation.score() - model.score(&entry)).abs() < 1e-12);
+            let tokens = explanation
+                .contributions
+                .iter()
+                .map(|(token, _)| token.as_str())
+                .collect::<Vec<_>>();
+            assert!(tokens.contains(&"rust") && tokens.contains(&"deals"));
+            assert!(!tokens.contains(&"unseen"));
+        }
+    }
+}
@@ -0,0 +1,343 @@
+//! Offline evaluation of a model on the votes in FeedHistory.db, so changes
+//! to the tokenizer, alpha or the model itself can be compared on real data.
+
+use std::fmt;
+
+use crate::classifier::Classifier;
+use crate::EntryContent;
+
+/// How the votes are split into training and test sets.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Split {
+    /// Every vote is tested once, by a model trained on the other folds.
+    /// Vote `i` goes to fold `i % k`, so each fold spans the whole history.
+    KFold(usize),
+    /// Trains on the oldest votes and tests on the newest `test_fraction`,
+    /// the way the server uses a model.
+    TimeOrdered { test_fraction: f64 },
+}
+
+/// Thresholds the report gives accuracy, precision, recall and F1 at.
+pub const THRESHOLDS: [f64; 9] = [0.1, 0.2, 0.3, 0.4, 0.5, 0.6, 0.7, 0.8, 0.9];
+
+/// Number of equal-width score buckets in the calibration table.
+const CALIBRATION_BUCKETS: usize = 10;
+
+/// `(score, liked)` for every vote scored by a model that didn't train on it.
+pub fn predict(
+    votes: &[EntryContent],
+    split: Split,
+    new_model: impl Fn() -> Box<dyn Classifier>,
+) -> Vec<(f64, bool)> {
+    let mut predictions = Vec::new();
+    for (train, test) in folds(votes.len(), split) {
+        let mut model = new_model();
+        model.train(train.iter().map(|&i| votes[i].clone()).collect());
+        predictions.extend(
+            test.iter()
+                .map(|&i| (model.score_content(&votes[i]), votes[i].liked)),
+        );
+    }
+    predictions
+}
+
+/// Training and test indices of each round of `split`.
+fn folds(len: usize, split: Split) -> Vec<(Vec<usize>, Vec<usize>)> {
+    match split {
+        Split::KFold(k) => (0..k)
+            .map(|fold| (0..len).partition(|i| i % k != fold))
+            .collect(),
+        Split::TimeOrdered { test_fraction } => {
+            // at least one vote each side, however small the fraction
+            let test_len = ((len as f64 * test_fraction).round() as usize)
+                .clamp(1, len.saturating_sub(1).max(1))
+                .min(len);
+            let train_len = len - test_len;
+            vec![((0..train_len).collect(), (train_len..len).collect())]
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Metrics {
+    pub threshold: f64,
+    pub accuracy: f64,
+    pub precision: f64,
+    pub recall: f64,
+    pub f1: f64,
+}
+
+/// Predictions whose score fell in `[low, high)`, and how many were liked.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CalibrationBucket {
+    pub low: f64,
+    pub high: f64,
+    pub count: usize,
+    pub mean_score: f64,
+    pub liked_rate: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Report {
+    pub votes: usize,
+    pub liked: usize,
+    /// Probability that a liked entry scores above a disliked one.
+    pub roc_auc: f64,
+    pub at_thresholds: Vec<Metrics>,
+    pub calibration: Vec<CalibrationBucket>,
+}
+
+impl Report {
+    pub fn new(predictions: &[(f64, bool)]) -> Report {
+        Report {
+            votes: predictions.len(),
+            liked: predictions.iter().filter(|(_, liked)| *liked).count(),
+            roc_auc: roc_auc(predictions),
+            at_thresholds: THRESHOLDS
+                .iter()
+                .map(|&threshold| metrics(predictions, threshold))
+                .collect(),
+            calibration: calibration(predictions),
+        }
+    }
+}
+
+/// Counts a score at or above `threshold` as predicting a like. Ratios with
+/// nothing to divide by are 0.
+pub fn metrics(predictions: &[(f64, bool)], threshold: f64) -> Metrics {
+    let (mut tp, mut fp, mut tn, mut fn_) = (0., 0., 0., 0.);
+    for &(score, liked) in predictions {
+        match (score >= threshold, liked) {
+            (true, true) => tp += 1.,
+            (true, false) => fp += 1.,
+            (false, false) => tn += 1.,
+            (false, true) => fn_ += 1.,
+        }
+    }
+    let ratio = |a: f64, b: f64| if b == 0. { 0. } else { a / b };
+    let precision = ratio(tp, tp + fp);
+    let recall = ratio(tp, tp + fn_);
+    Metrics {
+        threshold,
+        accuracy: ratio(tp + tn, tp + fp + tn + fn_),
+        precision,
+        recall,
+        f1: ratio(2. * precision * recall, precision + recall),
+    }
+}
+
+/// Area under the ROC curve, from the ranks of the scores (Mann-Whitney U),
+/// ties counting half. 0.5 when one of the classes is missing.
+pub fn roc_auc(predictions: &[(f64, bool)]) -> f64 {
+    let mut sorted = predictions.to_vec();
+    sorted.sort_by(|(a, _), (b, _)| a.total_cmp(b));
+
+    let liked = sorted.iter().filter(|(_, liked)| *liked).count() as f64;
+    let disliked = sorted.len() as f64 - liked;
+    if liked == 0. || disliked == 0. {
+        return 0.5;
+    }
+
+    let mut liked_rank_sum = 0.;
+    let mut start = 0;
+    while start < sorted.len() {
+        let end = start
+            + sorted[start..]
+                .iter()
+                .take_while(|(score, _)| *score == sorted[start].0)
+                .count();
+        // Tied scores share the mean of their 1-based ranks.
+        let rank = (start + 1 + end) as f64 / 2.;
+        liked_rank_sum += rank * sorted[start..end].iter().filter(|(_, l)| *l).count() as f64;
+        start = end;
+    }
+    (liked_rank_sum - liked * (liked + 1.) / 2.) / (liked * disliked)
+}
+
+fn calibration(predictions: &[(f64, bool)]) -> Vec<CalibrationBucket> {
+    let width = 1. / CALIBRATION_BUCKETS as f64;
+    (0..CALIBRATION_BUCKETS)
+        .map(|bucket| {
+            let low = bucket as f64 * width;
+            let high = low + width;
+            let inside = predictions
+                .iter()
+                .filter(|(score, _)| {
+                    *score >= low && (*score < high || bucket == CALIBRATION_BUCKETS - 1)
+                })
+                .collect::<Vec<_>>();
+            let count = inside.len();
+            let mean = |values: &mut dyn Iterator<Item = f64>| {
+                if count == 0 {
+                    0.
+                } else {
+                    values.sum::<f64>() / count as f64
+                }
+            };
+            CalibrationBucket {
+                low,
+                high,
+                count,
+                mean_score: mean(&mut inside.iter().map(|(score, _)| *score)),
+                liked_rate: mean(&mut inside.iter().map(|(_, liked)| f64::from(*liked as u8))),
+            }
+        })
+        .collect()
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{} votes, {} liked", self.votes, self.liked)?;
+        writeln!(f, "ROC-AUC {:.3}", self.roc_auc)?;
+        writeln!(f)?;
+        writeln!(
+            f,
+            "{:>9} {:>9} {:>9} {:>9} {:>9}",
+            "threshold", "accuracy", "precision", "recall", "f1"
+        )?;
+        for m in &self.at_thresholds {
+            writeln!(
+                f,
+                "{:>9.1} {:>9.3} {:>9.3} {:>9.3} {:>9.3}",
+                m.threshold, m.accuracy, m.precision, m.recall, m.f1
+            )?;
+        }
+        writeln!(f)?;
+        writeln!(
+            f,
+            "{:>11} {:>6} {:>10} {:>10}",
+            "score", "count", "mean score", "liked rate"
+        )?;
+        for b in &self.calibration {
+            writeln!(
+                f,
+                "{:>11} {:>6} {:>10.3} {:>10.3}",
+                format!("{:.1} - {:.1}", b.low, b.high),
+                b.count,
+                b.mean_score,
+                b.liked_rate
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::classifier::ModelKind;
+    use crate::tokenizer::TokenizerConfig;
+
+    #[test]
+    fn test_metrics_of_a_known_confusion_matrix() {
+        let predictions = [
+            (0.9, true),
+            (0.8, true),
+            (0.7, false),
+            (0.4, true),
+            (0.2, false),
+            (0.1, false),
+        ];
+        let m = metrics(&predictions, 0.5);
+
+        // tp 2, fp 1, tn 2, fn 1
+        assert_eq!(m.accuracy, 4. / 6.);
+        assert_eq!(m.precision, 2. / 3.);
+        assert_eq!(m.recall, 2. / 3.);
+        assert!((m.f1 - 2. / 3.).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_roc_auc() {
+        // 8 of the 9 liked/disliked pairs are ordered right.
+        let predictions = [
+            (0.9, true),
+            (0.8, true),
+            (0.7, false),
+            (0.4, true),
+            (0.2, false),
+            (0.1, false),
+        ];
+        assert!((roc_auc(&predictions) - 8. / 9.).abs() < 1e-12);
+
+        let ties = [(0.5, true), (0.5, false)];
+        assert_eq!(roc_auc(&ties), 0.5);
+        assert_eq!(roc_auc(&[(0.5, true)]), 0.5);
+    }
+
+    #[test]
+    fn test_calibration_buckets() {
+        let predictions = [(0.05, false), (0.15, false), (0.12, true), (1., true)];
+        let calibration = calibration(&predictions);
+
+        assert_eq!(calibration.len(), CALIBRATION_BUCKETS);
+        assert_eq!(calibration[0].count, 1);
+        assert_eq!(calibration[1].count, 2);
+        assert_eq!(calibration[1].liked_rate, 0.5);
+        assert_eq!(calibration[9].count, 1);
+        assert_eq!(calibration[9].mean_score, 1.);
+    }
+
+    #[test]
+    fn test_splits_never_test_on_training_votes() {
+        for (train, test) in folds(10, Split::KFold(3)) {
+            assert!(train.iter().all(|i| !test.contains(i)));
+            assert_eq!(train.len() + test.len(), 10);
+        }
+        let tested = folds(10, Split::KFold(3))
+            .into_iter()
+            .map(|(_, test)| test.len())
+            .sum::<usize>();
+        assert_eq!(tested, 10);
+
+        assert_eq!(
+            folds(10, Split::TimeOrdered { test_fraction: 0.2 }),
+            [((0..8).collect(), vec![8, 9])]
+        );
+        assert_eq!(
+            folds(
+                10,
+                Split::TimeOrdered {
+                    test_fraction: 0.01
+                }
+            ),
+            [((0..9).collect(), vec![9])]
+        );
+        assert_eq!(
+            folds(
+                10,
+                Split::TimeOrdered {
+                    test_fraction: 0.99
+                }
+            ),
+            [(vec![0], (1..10).collect())]
+        );
+    }
+
+    #[test]
+    fn test_separable_votes_are_predicted() {
+        let votes = (0..40)
+            .map(|i| EntryContent {
+                text: if i % 2 == 0 {
+                    "rust async"
+                } else {
+                    "crypto deals"
+                }
+                .to_string(),
+                liked: i % 2 == 0,
+                ..Default::default()
+            })
+            .collect::<Vec<_>>();
+
+        for kind in ModelKind::ALL {
+            let predictions = predict(&votes, Split::KFold(4), || {
+                kind.build(TokenizerConfig::default().build().unwrap())
+            });
+            let report = Report::new(&predictions);
+
+            assert_eq!(report.votes, 40);
+            assert_eq!(report.roc_auc, 1.);
+            assert_eq!(report.at_thresholds[4].accuracy, 1.);
+        }
+    }
+}
@@ -0,0 +1,99 @@
+//! Sometimes showing entries the classifier would hide. Its mistakes among
+//! hidden entries never get a vote otherwise, so they would never be fixed.
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+/// How often entries scored below the threshold are shown anyway, chosen
+/// with the `exploration` rocket config key.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "policy", rename_all = "kebab-case")]
+pub enum Exploration {
+    /// Only entries at or above the threshold are shown.
+    #[default]
+    Off,
+    /// Shows each hidden entry with probability `epsilon`, whatever its
+    /// score.
+    EpsilonGreedy { epsilon: f64 },
+    /// Adds normal noise with standard deviation `spread` to the log-odds of
+    /// the score and shows the entry if that lands above the threshold, so
+    /// entries the model is unsure about are explored the most.
+    Thompson { spread: f64 },
+}
+
+impl Exploration {
+    /// Whether to show an entry scored `score`, below `threshold`, anyway.
+    pub fn explores(&self, score: f64, threshold: f64, rng: &mut impl Rng) -> bool {
+        match *self {
+            Exploration::Off => false,
+            Exploration::EpsilonGreedy { epsilon } => rng.gen_bool(epsilon.clamp(0., 1.)),
+            Exploration::Thompson { spread } => {
+                let sampled = logit(score) + spread * standard_normal(rng);
+                sampled >= logit(threshold)
+            }
+        }
+    }
+}
+
+fn logit(p: f64) -> f64 {
+    let p = p.clamp(1e-9, 1. - 1e-9);
+    (p / (1. - p)).ln()
+}
+
+/// Box-Muller transform.
+fn standard_normal(rng: &mut impl Rng) -> f64 {
+    let u1: f64 = 1. - rng.gen::<f64>();
+    let u2: f64 = rng.gen();
+    (-2. * u1.ln()).sqrt() * (2. * std::f64::consts::PI * u2).cos()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    fn _explored(policy: Exploration, score: f64) -> f64 {
+        let mut rng = StdRng::seed_from_u64(7);
+        let draws = 10_000;
+        let explored = (0..draws)
+            .filter(|_| policy.explores(score, 0.5, &mut rng))
+            .count();
+        explored as f64 / draws as f64
+    }
+
+    #[test]
+    fn test_epsilon_greedy_explores_at_its_rate() {
+        assert_eq!(_explored(Exploration::Off, 0.4), 0.);
+        assert_eq!(
+            _explored(Exploration::EpsilonGreedy { epsilon: 0. }, 0.4),
+            0.
+        );
+        assert_eq!(
+            _explored(Exploration::EpsilonGreedy { epsilon: 1. }, 0.),
+            1.
+        );
+
+        let rate = _explored(Exploration::EpsilonGreedy { epsilon: 0.1 }, 0.01);
+        assert!((rate - 0.1).abs() < 0.02, "{}", rate);
+    }
+
+    #[test]
+    fn test_thompson_favours_uncertain_entries() {
+        let policy = Exploration::Thompson { spread: 1. };
+        let unsure = _explored(policy, 0.45);
+        let confident = _explored(policy, 0.05);
+
+        // P(N(0, 1) >= 0.2) and P(N(0, 1) >= 2.94)
+        assert!((unsure - 0.42).abs() < 0.02, "{}", unsure);
+        assert!(confident < 0.01, "{}", confident);
+    }
+
+    #[test]
+    fn test_policy_from_config() {
+        let policy: Exploration =
+            serde_json::from_str(r#"{"policy": "epsilon-greedy", "epsilon": 0.05}"#).unwrap();
+        assert_eq!(policy, Exploration::EpsilonGreedy { epsilon: 0.05 });
+        let policy: Exploration = serde_json::from_str(r#"{"policy": "off"}"#).unwrap();
+        assert_eq!(policy, Exploration::Off);
+    }
+}
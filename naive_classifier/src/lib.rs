@@ -1,7 +1,22 @@
 use feed_rs::model::Entry;
-use regex::Regex;
-use rusqlite::{Connection, Row};
+use rusqlite::{params, Row};
+use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+use url::Url;
+
+pub mod active;
+pub mod classifier;
+pub mod eval;
+pub mod exploration;
+pub mod logistic;
+pub mod multinomial;
+pub mod structure;
+pub mod tokenizer;
+
+use classifier::{Classifier, Explanation, ModelKind, MODEL_FORMAT_VERSION};
+use tokenizer::{Tokenizer, TokenizerConfig};
 
 pub struct NaiveBayesClassifier {
     pub alpha: f64,
@@ -11,71 +26,349 @@ pub struct NaiveBayesClassifier {
     pub disliked_entries_count: i32,
     pub liked_entries_count: i32,
     pub is_prepared: bool,
+    pub trained_up_to: i64,
+    tokenizer: Box<dyn Tokenizer>,
+    compiled: CompiledModel,
+}
+
+/// What scoring needs beyond the counts of an entry's own tokens, kept up to
+/// date token by token so a vote doesn't go over the whole vocabulary.
+#[derive(Debug, Clone, Default)]
+struct CompiledModel {
+    /// Log-odds of liking an entry that has none of the vocabulary's tokens:
+    /// the prior plus, for every token, ln P(absent | liked) - ln P(absent | disliked)
+    /// times the weight of the token's field.
+    absent_log_odds: f64,
+    liked_weights: WeightByCount,
+    disliked_weights: WeightByCount,
+}
+
+/// The summed field weight of the tokens found in each number of a class's
+/// entries: tokens with the same count are equally likely to be absent, so
+/// `absent_log_odds` only sums over the counts.
+#[derive(Debug, Clone, Default)]
+struct WeightByCount(HashMap<i32, f64>);
+
+impl WeightByCount {
+    fn add(&mut self, count: i32, weight: f64) {
+        *self.0.entry(count).or_default() += weight;
+    }
+
+    fn moved(&mut self, from: i32, to: i32, weight: f64) {
+        self.add(from, -weight);
+        self.add(to, weight);
+    }
 }
 
 type PossiblyLiked = bool;
 
-#[derive(Debug, Clone, PartialEq)]
+/// Votes needed before the classifier starts filtering entries out.
+pub const MIN_TRAINING_ENTRIES: i32 = 100;
+
+#[derive(Serialize, Deserialize)]
+struct SavedModel {
+    format_version: u32,
+    model: ModelKind,
+    tokenizer: TokenizerConfig,
+    alpha: f64,
+    liked_entries_count: i32,
+    disliked_entries_count: i32,
+    token_liked_counts: HashMap<String, i32>,
+    token_disliked_counts: HashMap<String, i32>,
+    trained_up_to: i64,
+}
+
+/// What the classifier learns from an entry, built the same way from a
+/// fetched [`Entry`] and from a row of `already_seen`.
+#[derive(Debug, Clone, Default, PartialEq)]
 pub struct EntryContent {
-    all_content: String,
+    title: String,
+    /// Summary and content.
+    text: String,
+    authors: Vec<String>,
+    categories: Vec<String>,
+    /// Url of the feed the entry came from, which feed-sync keeps in
+    /// [`Entry::source`].
+    feed: Option<String>,
+    links: Vec<String>,
+    /// Media files attached to the entry, e.g. podcast episodes.
+    enclosures: usize,
+    language: Option<String>,
     liked: PossiblyLiked,
 }
 
 impl EntryContent {
+    pub fn from_entry(entry: &Entry, liked: PossiblyLiked) -> EntryContent {
+        let title = entry.title.as_ref().map_or("", |t| t.content.as_str());
+        let summary = entry.summary.as_ref().map_or("", |s| s.content.as_str());
+        let content = entry
+            .content
+            .as_ref()
+            .and_then(|c| c.body.as_deref())
+            .unwrap_or_default();
+
+        EntryContent {
+            title: title.to_string(),
+            text: format!("{} {}", summary, content),
+            authors: entry.authors.iter().map(|a| a.name.clone()).collect(),
+            categories: entry.categories.iter().map(|c| c.term.clone()).collect(),
+            feed: entry.source.clone(),
+            links: entry.links.iter().map(|l| l.href.clone()).collect(),
+            enclosures: entry.media.iter().map(|m| m.content.len()).sum::<usize>()
+                + entry
+                    .links
+                    .iter()
+                    .filter(|l| l.rel.as_deref() == Some("enclosure"))
+                    .count(),
+            language: entry.language.clone(),
+            liked,
+        }
+    }
+
     pub fn from_row(row: &Row) -> EntryContent {
-        let title: String = row.get("title").unwrap_or_default();
         let summary: String = row.get("summary").unwrap_or_default();
         let content: String = row.get("content").unwrap_or_default();
-        let authors: String = row.get("authors").unwrap_or_default();
-        let categories: String = row.get("categories").unwrap_or_default();
-        let link: String = row.get("link").unwrap_or_default();
 
-        let all_content = format!(
-            "{} {} {} {} {} {}",
-            title, summary, content, authors, categories, link
-        );
+        EntryContent {
+            title: row.get("title").unwrap_or_default(),
+            text: format!("{} {}", summary, content),
+            authors: Self::names(row, "authors", "name"),
+            categories: Self::names(row, "categories", "term"),
+            feed: row.get("feed_url").unwrap_or_default(),
+            links: Self::names(row, "links", "href"),
+            enclosures: Self::array(row, "media")
+                .iter()
+                .map(|media| media["content"].as_array().map_or(0, Vec::len))
+                .sum::<usize>()
+                + Self::array(row, "links")
+                    .iter()
+                    .filter(|link| link["rel"] == "enclosure")
+                    .count(),
+            language: row
+                .get::<_, Option<String>>("language")
+                .unwrap_or_default()
+                .filter(|language| !language.is_empty()),
+            liked: row.get("is_liked").unwrap(),
+        }
+    }
+
+    /// `field` of every object in the JSON array stored in `column`, e.g. the
+    /// author names out of the serialized feed-rs `Person`s.
+    fn names(row: &Row, column: &str, field: &str) -> Vec<String> {
+        Self::array(row, column)
+            .iter()
+            .filter_map(|value| value[field].as_str())
+            .map(str::to_string)
+            .collect()
+    }
 
-        let liked = row.get("is_liked").unwrap();
-        EntryContent { all_content, liked }
+    /// The JSON array stored in `column`, empty when there is none.
+    fn array(row: &Row, column: &str) -> Vec<serde_json::Value> {
+        let json: String = row.get(column).unwrap_or_default();
+        match serde_json::from_str::<serde_json::Value>(&json) {
+            Ok(serde_json::Value::Array(values)) => values,
+            _ => Vec::new(),
+        }
+    }
+
+    /// The features the classifier sees, for training and classifying alike,
+    /// prefixed by the field they come from: what `tokenizer` makes of the
+    /// title as `title:` features and of the summary and content as is, every
+    /// author and category whole as `author:` and `category:` features, the
+    /// feed as a `feed:` feature, a `domain:` feature and `path:` features
+    /// for every link, and, when the tokenizer asks for it, the entry's shape
+    /// as [`structure`] features.
+    pub fn features(&self, tokenizer: &dyn Tokenizer) -> HashSet<String> {
+        self.feature_counts(tokenizer).into_keys().collect()
+    }
+
+    /// [`EntryContent::features`] with how many times each occurs.
+    pub fn feature_counts(&self, tokenizer: &dyn Tokenizer) -> HashMap<String, usize> {
+        let language = self.language.as_deref();
+        let title = tokenizer
+            .tokenize(&self.title, language)
+            .into_iter()
+            .map(|token| format!("title:{}", token));
+        let text = tokenizer.tokenize(&self.text, language);
+        let authors = self
+            .authors
+            .iter()
+            .filter_map(|a| Self::phrase("author", a));
+        let categories = self
+            .categories
+            .iter()
+            .filter_map(|c| Self::phrase("category", c));
+        let feed = self
+            .feed
+            .iter()
+            .map(|feed| format!("feed:{}", feed.to_lowercase()));
+        let links = self.links.iter().flat_map(|link| Self::link_features(link));
+        let structure = if tokenizer.config().structure {
+            structure::features(self)
+        } else {
+            Vec::new()
+        };
+
+        let mut counts = HashMap::new();
+        for feature in title
+            .chain(text)
+            .chain(authors)
+            .chain(categories)
+            .chain(feed)
+            .chain(links)
+            .chain(structure)
+        {
+            *counts.entry(feature).or_default() += 1;
+        }
+        counts
+    }
+
+    /// `text` as a single lowercased `field:` feature, whitespace collapsed,
+    /// so "Jane  Doe" is `author:jane doe` and not two words.
+    fn phrase(field: &str, text: &str) -> Option<String> {
+        let words = text.split_whitespace().collect::<Vec<_>>();
+        if words.is_empty() {
+            return None;
+        }
+        Some(format!("{}:{}", field, words.join(" ").to_lowercase()))
+    }
+
+    fn link_features(href: &str) -> Vec<String> {
+        let href = href.to_lowercase();
+        let Ok(url) = Url::parse(&href) else {
+            return Self::path_features(&href).collect();
+        };
+        let host = url
+            .host_str()
+            .map(|host| format!("domain:{}", host.trim_start_matches("www.")));
+        host.into_iter()
+            .chain(Self::path_features(url.path()))
+            .collect()
+    }
+
+    fn path_features(path: &str) -> impl Iterator<Item = String> + '_ {
+        tokenizer::words(path).map(|token| format!("path:{}", token))
     }
 }
 
+/// Matches the `already_seen` rows of the same entry as `vote`.
+const SAME_ENTRY: &str = "later.feed_url IS vote.feed_url AND later.id = vote.id";
+
+/// The `already_seen` rows after `rowid`, oldest vote first, with their rowid.
+/// An entry voted on again only counts with its last vote.
+pub fn votes_since(
+    db_path: &str,
+    rowid: i64,
+) -> Result<Vec<(i64, EntryContent)>, Box<dyn std::error::Error>> {
+    let conn = feed_sync::schema::open(db_path)?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT rowid, * FROM already_seen AS vote
+         WHERE rowid > ?1 AND NOT EXISTS (
+             SELECT 1 FROM already_seen AS later
+             WHERE {} AND later.rowid > vote.rowid
+         )
+         ORDER BY rowid",
+        SAME_ENTRY
+    ))?;
+    let rows = stmt
+        .query_map(params![rowid], |row| {
+            Ok((row.get::<_, i64>(0)?, EntryContent::from_row(row)))
+        })?
+        .collect::<Result<Vec<_>, rusqlite::Error>>()?;
+    Ok(rows)
+}
+
+/// Whether `db_path` still holds the votes a model learned: `votes_seen`
+/// of them up to rowid `trained_up_to`, none voted on again since. When it
+/// doesn't, e.g. because the db was reset or replaced, the model has to be
+/// trained again from scratch.
+pub fn holds_learned_votes(
+    db_path: &str,
+    trained_up_to: i64,
+    votes_seen: i32,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    let conn = feed_sync::schema::open(db_path)?;
+    let (learned, revoted) = conn.query_row(
+        &format!(
+            "SELECT
+                 (SELECT COUNT(*) FROM already_seen AS vote
+                  WHERE rowid <= ?1 AND NOT EXISTS (
+                      SELECT 1 FROM already_seen AS later
+                      WHERE {0} AND later.rowid > vote.rowid AND later.rowid <= ?1
+                  )),
+                 EXISTS (
+                     SELECT 1 FROM already_seen AS vote JOIN already_seen AS later
+                     ON {0}
+                     WHERE vote.rowid <= ?1 AND later.rowid > ?1
+                 )",
+            SAME_ENTRY
+        ),
+        params![trained_up_to],
+        |row| Ok((row.get::<_, i64>(0)?, row.get::<_, bool>(1)?)),
+    )?;
+    Ok(learned == votes_seen as i64 && !revoted)
+}
+
 impl NaiveBayesClassifier {
     pub fn new(db_path: &str) -> Result<NaiveBayesClassifier, Box<dyn std::error::Error>> {
-        let conn = Connection::open(db_path)?;
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS already_seen (
-                id TEXT PRIMARY KEY,
-                title TEXT,
-                authors TEXT,
-                content TEXT,
-                links TEXT,
-                summary TEXT,
-                categories TEXT,
-                language TEXT,
-                is_liked INTEGER
-            )",
-            [],
-        )?;
-        let mut stmt = conn.prepare("SELECT * FROM already_seen")?;
-        let entry_contents = stmt
-            .query_map([], |row| Ok(EntryContent::from_row(row)))?
-            .collect::<Vec<Result<EntryContent, rusqlite::Error>>>();
-
-        let entry_contents = entry_contents
-            .into_iter()
-            .map(|entry_content| entry_content.unwrap())
-            .collect::<Vec<EntryContent>>();
-
         let mut classifier = NaiveBayesClassifier::new_classifier(1.0);
-        classifier.is_prepared = entry_contents.len() > 100;
-        if classifier.is_prepared {
-            classifier.train(entry_contents);
+        classifier.fold_in(votes_since(db_path, 0)?);
+        Ok(classifier)
+    }
+
+    pub fn save_model(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        let model = SavedModel {
+            format_version: MODEL_FORMAT_VERSION,
+            model: ModelKind::NaiveBayes,
+            tokenizer: self.tokenizer.config().clone(),
+            alpha: self.alpha,
+            liked_entries_count: self.liked_entries_count,
+            disliked_entries_count: self.disliked_entries_count,
+            token_liked_counts: self.token_liked_counts.clone(),
+            token_disliked_counts: self.token_disliked_counts.clone(),
+            trained_up_to: self.trained_up_to,
+        };
+        classifier::write_model(path, &model)
+    }
+
+    pub fn load_model(path: &Path) -> Result<NaiveBayesClassifier, Box<dyn std::error::Error>> {
+        Self::from_saved(&fs::read(path)?)
+    }
+
+    pub(crate) fn from_saved(
+        bytes: &[u8],
+    ) -> Result<NaiveBayesClassifier, Box<dyn std::error::Error>> {
+        classifier::check_saved(bytes, ModelKind::NaiveBayes)?;
+        let model: SavedModel = serde_json::from_slice(bytes)?;
+        let mut classifier =
+            NaiveBayesClassifier::with_tokenizer(model.alpha, model.tokenizer.build()?);
+        classifier.tokens = model.token_liked_counts.keys().cloned().collect();
+        classifier.liked_entries_count = model.liked_entries_count;
+        classifier.disliked_entries_count = model.disliked_entries_count;
+        classifier.token_liked_counts = model.token_liked_counts;
+        classifier.token_disliked_counts = model.token_disliked_counts;
+        classifier.trained_up_to = model.trained_up_to;
+        let fields = &classifier.tokenizer.config().fields;
+        for (token, count) in &classifier.token_liked_counts {
+            let weight = fields.weight(token);
+            classifier.compiled.liked_weights.add(*count, weight);
+            let disliked = classifier
+                .token_disliked_counts
+                .get(token)
+                .copied()
+                .unwrap_or(0);
+            classifier.compiled.disliked_weights.add(disliked, weight);
         }
+        classifier.counts_changed();
         Ok(classifier)
     }
 
-    pub(crate) fn new_classifier(alpha: f64) -> NaiveBayesClassifier {
+    /// An untrained classifier, `alpha` being the Laplace smoothing.
+    pub fn new_classifier(alpha: f64) -> NaiveBayesClassifier {
+        NaiveBayesClassifier::with_tokenizer(alpha, TokenizerConfig::default().build().unwrap())
+    }
+
+    pub fn with_tokenizer(alpha: f64, tokenizer: Box<dyn Tokenizer>) -> NaiveBayesClassifier {
         NaiveBayesClassifier {
             alpha,
             tokens: HashSet::new(),
@@ -84,149 +377,648 @@ impl NaiveBayesClassifier {
             disliked_entries_count: 0,
             liked_entries_count: 0,
             is_prepared: false,
+            trained_up_to: 0,
+            tokenizer,
+            compiled: CompiledModel::default(),
         }
     }
 
-    pub fn train(&mut self, data: Vec<EntryContent>) {
-        for entry in data.iter() {
-            self.increment_entry_classifications_count(entry);
-            for token in Self::tokenize(&entry.all_content) {
-                self.tokens.insert(token.to_string());
-                self.increment_token_count(token, entry.liked)
-            }
-        }
+    fn counts_changed(&mut self) {
+        self.is_prepared |=
+            self.liked_entries_count + self.disliked_entries_count > MIN_TRAINING_ENTRIES;
+        self.compile();
     }
 
+    /// Posterior probability that the user likes `entry`, P(liked | entry),
+    /// under a Bernoulli naive Bayes model: each token of the vocabulary is a
+    /// present/absent feature, with Laplace-smoothed (`alpha`) per-class
+    /// likelihoods and class priors taken from the vote counts. Tokens never
+    /// seen in training are ignored. Returns 1 while the classifier isn't
+    /// prepared, so nothing is filtered out.
     pub fn classify(&self, entry: Entry) -> f64 {
-        if !self.is_prepared {
-            return 1.;
-        }
-        let link = match entry.links.get(0) {
-            Some(link) => link.href.to_lowercase(),
-            None => String::new(),
-        };
-        let text = format!(
-            "{} {} {} {} {} {}",
-            entry.title.unwrap_or_default().content,
-            entry.summary.unwrap_or_default().content,
-            entry.content.unwrap_or_default().body.unwrap_or_default(),
-            entry
-                .authors
-                .iter()
-                .map(|a| a.name.as_str())
-                .collect::<Vec<_>>()
-                .join(" "),
-            entry
-                .categories
-                .iter()
-                .map(|c| c.term.as_str())
-                .collect::<Vec<_>>()
-                .join(" "),
-            link,
-        );
-        let lower_case_text = text.to_lowercase();
-        let message_tokens = Self::tokenize(&lower_case_text);
-        let (prob_if_dislike, prob_if_liked) = self.probabilities_of_message(message_tokens);
-        return prob_if_liked / (prob_if_liked + prob_if_dislike);
-    }
-    fn probabilities_of_message(&self, message_tokens: HashSet<&str>) -> (f64, f64) {
-        let mut log_prob_if_dislike = 0.;
-        let mut log_prob_if_like = 0.;
-        let epsilon = 1e-9;
-
-        for token in self.tokens.iter() {
-            let (prob_if_disliked, prob_if_like) = self.probabilites_of_token(&token);
-
-            let prob_if_disliked = prob_if_disliked.max(epsilon).min(1. - epsilon);
-            let prob_if_like = prob_if_like.max(epsilon).min(1. - epsilon);
+        self.score(&entry)
+    }
 
-            if message_tokens.contains(token.as_str()) {
-                log_prob_if_dislike += prob_if_disliked.ln();
-                log_prob_if_like += prob_if_like.ln();
-            } else {
-                log_prob_if_dislike += (1. - prob_if_disliked).ln();
-                log_prob_if_like += (1. - prob_if_like).ln();
-            }
+    fn compile(&mut self) {
+        let total = (self.liked_entries_count + self.disliked_entries_count) as f64;
+        if total == 0. {
+            self.compiled.absent_log_odds = 0.;
+            return;
         }
-
-        let prob_if_dislike = log_prob_if_dislike.exp();
-        let prob_if_like = log_prob_if_like.exp();
-
-        return (prob_if_dislike, prob_if_like);
+        let prior = (self.liked_entries_count as f64 / total).ln()
+            - (self.disliked_entries_count as f64 / total).ln();
+        let log_absent = |weights: &WeightByCount, entries: i32| {
+            weights
+                .0
+                .iter()
+                .map(|(count, weight)| weight * (1. - self.probability(*count, entries)).ln())
+                .sum::<f64>()
+        };
+        self.compiled.absent_log_odds = prior
+            + log_absent(&self.compiled.liked_weights, self.liked_entries_count)
+            - log_absent(&self.compiled.disliked_weights, self.disliked_entries_count);
     }
 
-    fn probabilites_of_token(&self, token: &str) -> (f64, f64) {
-        let prob_of_token_disliked = (self.token_disliked_counts[token] as f64 + self.alpha)
-            / (self.liked_entries_count as f64 + 2. * self.alpha);
+    /// P(token present | class) for a token found in `count` of the class's
+    /// `entries`, smoothed by `alpha` and kept off 0 and 1.
+    fn probability(&self, count: i32, entries: i32) -> f64 {
+        let epsilon = 1e-9;
+        ((count as f64 + self.alpha) / (entries as f64 + 2. * self.alpha))
+            .max(epsilon)
+            .min(1. - epsilon)
+    }
 
-        let prob_of_token_liked = (self.token_liked_counts[token] as f64 + self.alpha)
-            / (self.liked_entries_count as f64 + 2. * self.alpha);
+    /// What the presence of `token` adds to `absent_log_odds`, `None` for a
+    /// token never seen in training.
+    fn present_log_odds(&self, token: &str) -> Option<f64> {
+        let liked = self.probability(
+            *self.token_liked_counts.get(token)?,
+            self.liked_entries_count,
+        );
+        let disliked = self.probability(
+            self.token_disliked_counts.get(token).copied().unwrap_or(0),
+            self.disliked_entries_count,
+        );
+        let if_absent = (1. - liked).ln() - (1. - disliked).ln();
+        let if_present = liked.ln() - disliked.ln();
+        // a field weighs its tokens whether present or absent, so a weight of
+        // 0 leaves them out of the model altogether
+        Some(self.tokenizer.config().fields.weight(token) * (if_present - if_absent))
+    }
 
-        return (prob_of_token_disliked, prob_of_token_liked);
+    /// What each known token of `content` adds to `absent_log_odds`.
+    fn contributions<'a>(
+        &'a self,
+        content: &EntryContent,
+    ) -> impl Iterator<Item = (String, f64)> + 'a {
+        content
+            .features(self.tokenizer.as_ref())
+            .into_iter()
+            .filter_map(|token| {
+                let delta = self.present_log_odds(&token)?;
+                Some((token, delta))
+            })
     }
 
     fn increment_entry_classifications_count(&mut self, entry: &EntryContent) {
         if entry.liked {
-            self.disliked_entries_count += 1;
-        } else {
             self.liked_entries_count += 1;
+        } else {
+            self.disliked_entries_count += 1;
         }
     }
 
     fn increment_token_count(&mut self, token: &str, liked: bool) {
+        let weight = self.tokenizer.config().fields.weight(token);
         if !self.token_disliked_counts.contains_key(token) {
             self.token_disliked_counts.insert(token.to_string(), 0);
+            self.compiled.disliked_weights.add(0, weight);
         }
 
         if !self.token_liked_counts.contains_key(token) {
             self.token_liked_counts.insert(token.to_string(), 0);
+            self.compiled.liked_weights.add(0, weight);
         }
 
         if liked {
-            self.increment_liked_count(token);
+            self.increment_liked_count(token, weight);
         } else {
-            self.increment_disliked_count(token);
+            self.increment_disliked_count(token, weight);
         }
     }
 
-    fn increment_disliked_count(&mut self, token: &str) {
-        *self.token_disliked_counts.get_mut(token).unwrap() += 1;
+    fn increment_disliked_count(&mut self, token: &str, weight: f64) {
+        let count = self.token_disliked_counts.get_mut(token).unwrap();
+        self.compiled
+            .disliked_weights
+            .moved(*count, *count + 1, weight);
+        *count += 1;
     }
 
-    fn increment_liked_count(&mut self, token: &str) {
-        *self.token_liked_counts.get_mut(token).unwrap() += 1;
+    fn increment_liked_count(&mut self, token: &str, weight: f64) {
+        let count = self.token_liked_counts.get_mut(token).unwrap();
+        self.compiled
+            .liked_weights
+            .moved(*count, *count + 1, weight);
+        *count += 1;
     }
+}
 
-    pub(crate) fn tokenize(lower_case_text: &str) -> HashSet<&str> {
-        Regex::new(r"[a-z0-9']+")
-            .unwrap()
-            .find_iter(lower_case_text)
-            .map(|mat| mat.as_str())
-            .collect()
+impl Classifier for NaiveBayesClassifier {
+    fn kind(&self) -> ModelKind {
+        ModelKind::NaiveBayes
+    }
+
+    /// Adds `data` to the counts. The classifier becomes prepared once it has
+    /// seen more than [`MIN_TRAINING_ENTRIES`] votes.
+    fn train(&mut self, data: Vec<EntryContent>) {
+        for entry in data.iter() {
+            self.increment_entry_classifications_count(entry);
+            for token in entry.features(self.tokenizer.as_ref()) {
+                self.increment_token_count(&token, entry.liked);
+                self.tokens.insert(token);
+            }
+        }
+        self.counts_changed();
+    }
+
+    fn is_prepared(&self) -> bool {
+        self.is_prepared
+    }
+
+    fn votes_seen(&self) -> i32 {
+        self.liked_entries_count + self.disliked_entries_count
+    }
+
+    fn score_content(&self, content: &EntryContent) -> f64 {
+        let log_odds = self
+            .contributions(content)
+            .fold(self.compiled.absent_log_odds, |sum, (_, delta)| sum + delta);
+        // P(like | x) = 1 / (1 + P(dislike, x) / P(like, x)), computed on the
+        // logs so long entries don't underflow both joints to zero.
+        1. / (1. + (-log_odds).exp())
+    }
+
+    fn explain(&self, entry: &Entry) -> Explanation {
+        let contributions = self
+            .contributions(&EntryContent::from_entry(entry, false))
+            .collect();
+        Explanation::new(self.compiled.absent_log_odds, contributions)
+    }
+
+    fn save(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        self.save_model(path)
+    }
+
+    fn tokenizer(&self) -> &dyn Tokenizer {
+        self.tokenizer.as_ref()
+    }
+
+    fn trained_up_to(&self) -> i64 {
+        self.trained_up_to
+    }
+
+    fn set_trained_up_to(&mut self, rowid: i64) {
+        self.trained_up_to = rowid;
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use feed_rs::model::Content;
+    use feed_rs::model::{Category, Content, Link, Person, Text};
+    use feed_sync::{FeedManager, QueuedEntry};
 
     use super::*;
+    use tokenizer::UnicodeTokenizer;
+
+    fn _link(href: &str) -> Link {
+        Link {
+            href: href.to_string(),
+            rel: None,
+            media_type: None,
+            href_lang: None,
+            title: None,
+            length: None,
+        }
+    }
+
+    fn _entry() -> Entry {
+        Entry {
+            id: "https://www.Example.com/Posts/rust-1-76".to_string(),
+            title: Some(Text {
+                content: "Rust 1.76 released".to_string(),
+                ..Default::default()
+            }),
+            summary: Some(Text {
+                content: "The Rust team is happy".to_string(),
+                ..Default::default()
+            }),
+            content: Some(Content {
+                body: Some("<p>Release notes</p>".to_string()),
+                ..Default::default()
+            }),
+            authors: vec![Person {
+                name: "Jane".to_string(),
+                uri: None,
+                email: None,
+            }],
+            categories: vec![Category {
+                term: "Rust".to_string(),
+                scheme: None,
+                label: None,
+                subcategories: Vec::new(),
+            }],
+            links: vec![
+                _link("https://www.Example.com/Posts/rust-1-76"),
+                _link("https://blog.rust-lang.org/2024/02/08/"),
+            ],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_features_are_prefixed_by_field() {
+        let mut entry = _entry();
+        entry.authors[0].name = "Jane  Doe".to_string();
+        let queued = QueuedEntry::new(entry, "https://www.example.com/RSS");
+        let features =
+            EntryContent::from_entry(&queued.entry, true).features(&UnicodeTokenizer::default());
+
+        for feature in [
+            "title:rust",
+            "title:released",
+            "rust",
+            "notes",
+            "author:jane doe",
+            "category:rust",
+            "feed:https://www.example.com/rss",
+            "domain:example.com",
+            "path:posts",
+            "path:rust",
+            "path:76",
+            "domain:blog.rust-lang.org",
+            "path:2024",
+        ] {
+            assert!(features.contains(feature), "missing {}", feature);
+        }
+        for feature in ["released", "jane", "title:team", "author:jane"] {
+            assert!(!features.contains(feature), "unexpected {}", feature);
+        }
+    }
+
+    #[test]
+    fn test_field_weights_scale_contributions() {
+        let train = |fields: tokenizer::FieldWeights| {
+            let config = TokenizerConfig {
+                fields,
+                ..Default::default()
+            };
+            let mut model = NaiveBayesClassifier::with_tokenizer(1., config.build().unwrap());
+            model.update(&_entry(), true);
+            model.update(&_text_entry("crypto"), false);
+            model.explain(&_entry())
+        };
+        let plain = train(Default::default());
+        let weighted = train(tokenizer::FieldWeights {
+            title: 2.,
+            path: 0.,
+            ..Default::default()
+        });
+
+        let contribution = |explanation: &Explanation, feature: &str| {
+            explanation
+                .contributions
+                .iter()
+                .find(|(f, _)| f == feature)
+                .map(|(_, c)| *c)
+                .unwrap()
+        };
+        assert!(contribution(&plain, "title:rust") > 0.);
+        assert_eq!(
+            contribution(&weighted, "title:rust"),
+            2. * contribution(&plain, "title:rust")
+        );
+        assert_eq!(contribution(&weighted, "path:posts"), 0.);
+        assert_eq!(
+            contribution(&weighted, "rust"),
+            contribution(&plain, "rust")
+        );
+    }
+
+    fn _temp_path(name: &str) -> String {
+        std::env::temp_dir()
+            .join(format!("{}-{}", std::process::id(), name))
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    /// A new db under the temp dir, upgraded like the server does at startup.
+    fn _temp_db(name: &str) -> String {
+        let db_path = _temp_path(name);
+        _remove_db(&db_path);
+        feed_sync::schema::upgrade(&db_path).unwrap();
+        db_path
+    }
+
+    fn _remove_db(db_path: &str) {
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{}", db_path, suffix));
+        }
+    }
+
+    fn _vote(db_path: &str, text: &str, liked: bool) {
+        let mut entry = _text_entry(text);
+        entry.id = text.to_string();
+        let queued = QueuedEntry::new(entry, "https://www.example.com/rss");
+        FeedManager::new()
+            .save_vote(db_path, &queued, liked)
+            .expect("Failed to save vote to the database");
+    }
+
+    #[test]
+    fn test_stored_entry_has_the_same_features() {
+        let db_path = _temp_db("features.db");
+        let queued = QueuedEntry::new(_entry(), "https://www.example.com/rss");
+        let entry = queued.entry.clone();
+        FeedManager::new()
+            .save_vote(&db_path, &queued, true)
+            .expect("Failed to save vote to the database");
+
+        let conn = feed_sync::schema::open(&db_path).unwrap();
+        let stored = conn
+            .query_row("SELECT * FROM already_seen", [], |row| {
+                Ok(EntryContent::from_row(row))
+            })
+            .unwrap();
+        drop(conn);
+        _remove_db(&db_path);
+
+        assert_eq!(stored, EntryContent::from_entry(&entry, true));
+        assert_eq!(
+            stored.features(&UnicodeTokenizer::default()),
+            EntryContent::from_entry(&entry, true).features(&UnicodeTokenizer::default())
+        );
+    }
+
+    #[test]
+    fn test_enclosures_are_counted_from_storage_too() {
+        let rss = r#"<rss version="2.0"><channel><title>t</title><item>
+            <guid>https://www.example.com/episode-1</guid>
+            <title>Episode 1</title>
+            <enclosure url="https://www.example.com/1.mp3" length="1" type="audio/mpeg"/>
+        </item></channel></rss>"#;
+        let entry = feed_rs::parser::parse(rss.as_bytes()).unwrap().entries[0].clone();
+        let mut queued = QueuedEntry::new(entry, "https://www.example.com/rss");
+        queued.entry.links.push(Link {
+            rel: Some("enclosure".to_string()),
+            .._link("https://www.example.com/1.ogg")
+        });
+        let db_path = _temp_db("enclosures.db");
+        FeedManager::new()
+            .save_vote(&db_path, &queued, true)
+            .expect("Failed to save vote to the database");
+
+        let conn = feed_sync::schema::open(&db_path).unwrap();
+        let stored = conn
+            .query_row("SELECT * FROM already_seen", [], |row| {
+                Ok(EntryContent::from_row(row))
+            })
+            .unwrap();
+        drop(conn);
+        _remove_db(&db_path);
+
+        assert_eq!(stored.enclosures, 2);
+        assert_eq!(stored, EntryContent::from_entry(&queued.entry, true));
+    }
+
+    #[test]
+    fn test_structure_features_are_explained() {
+        let config = TokenizerConfig {
+            structure: true,
+            ..Default::default()
+        };
+        let mut model = NaiveBayesClassifier::with_tokenizer(1., config.build().unwrap());
+        let shouting = _text_entry("BUY NOW <a href=x>BUY</a> <a href=y>NOW</a>");
+        model.update(&shouting, false);
+        model.update(&_entry(), true);
+
+        let explanation = model.explain(&_text_entry("CHEAP <a href=z>PILLS</a>"));
+        let toward_dislike = explanation
+            .toward_dislike(10)
+            .into_iter()
+            .map(|(feature, _)| feature)
+            .collect::<Vec<_>>();
+        assert!(toward_dislike.contains(&"structure:caps-high".to_string()));
+        assert!(toward_dislike.contains(&"structure:no-author".to_string()));
+        assert!(!EntryContent::from_entry(&shouting, false)
+            .features(&UnicodeTokenizer::default())
+            .iter()
+            .any(|feature| feature.starts_with("structure:")));
+    }
+
+    /// Content whose only text is `text`.
+    pub fn _content(text: &str, liked: bool) -> EntryContent {
+        EntryContent {
+            text: text.to_string(),
+            liked,
+            ..Default::default()
+        }
+    }
+
+    /// An entry whose only text is `text`, as content.
+    pub fn _text_entry(text: &str) -> Entry {
+        Entry {
+            content: Some(Content {
+                body: Some(text.to_string()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_posterior_of_worked_example() {
+        let mut model = NaiveBayesClassifier::new_classifier(1.);
+        model.train(vec![
+            _content("rust", true),
+            _content("rust async", true),
+            _content("crypto", false),
+        ]);
+        model.is_prepared = true;
+
+        // liked:    P = 2/3, P(rust) = 3/4, P(async) = 2/4, P(crypto) = 1/4
+        // disliked: P = 1/3, P(rust) = 1/3, P(async) = 1/3, P(crypto) = 2/3
+        // "rust":   2/3 * 3/4 * 2/4 * 3/4 = 3/16 vs 1/3 * 1/3 * 2/3 * 1/3 = 2/81
+        let expected = (3. / 16.) / (3. / 16. + 2. / 81.);
+        assert!((model.classify(_text_entry("Rust")) - expected).abs() < 1e-12);
+
+        // "crypto": 2/3 * 1/4 * 2/4 * 1/4 = 1/48 vs 1/3 * 2/3 * 2/3 * 2/3 = 8/81
+        let expected = (1. / 48.) / (1. / 48. + 8. / 81.);
+        assert!((model.classify(_text_entry("crypto")) - expected).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_posterior_does_not_underflow() {
+        let mut model = NaiveBayesClassifier::new_classifier(1.);
+        let words = (0..5000).map(|i| format!("w{}", i)).collect::<Vec<_>>();
+        model.train(vec![
+            _content(&words.join(" "), true),
+            _content("nothing in common", false),
+        ]);
+        model.is_prepared = true;
+
+        let score = model.classify(_text_entry(&words.join(" ")));
+        assert!(score > 0.99 && score <= 1.);
+    }
+
+    #[test]
+    fn test_update_matches_batch_training() {
+        let votes = [
+            ("rust async", true),
+            ("crypto deals", false),
+            ("rust", true),
+        ];
+        let mut batch = NaiveBayesClassifier::new_classifier(1.);
+        batch.train(votes.iter().map(|(t, l)| _content(t, *l)).collect());
+        let mut online = NaiveBayesClassifier::new_classifier(1.);
+        for (text, liked) in votes {
+            online.update(&_text_entry(text), liked);
+        }
+
+        assert_eq!(online.tokens, batch.tokens);
+        assert_eq!(online.token_liked_counts, batch.token_liked_counts);
+        assert_eq!(online.token_disliked_counts, batch.token_disliked_counts);
+        assert_eq!(online.liked_entries_count, 2);
+        assert_eq!(online.disliked_entries_count, 1);
+    }
+
+    #[test]
+    fn test_update_prepares_once_past_threshold() {
+        let mut model = NaiveBayesClassifier::new_classifier(1.);
+        for i in 0..MIN_TRAINING_ENTRIES {
+            model.update(&_text_entry("rust"), i % 2 == 0);
+        }
+        assert!(!model.is_prepared);
+        assert_eq!(model.votes_to_prepare(), 1);
+        assert_eq!(model.classify(_text_entry("crypto")), 1.);
+
+        model.update(&_text_entry("crypto"), false);
+        assert!(model.is_prepared);
+        assert_eq!(model.votes_to_prepare(), 0);
+        assert!(model.classify(_text_entry("crypto")) < 0.5);
+    }
+
+    #[test]
+    fn test_changed_votes_mean_retraining() {
+        let db_path = _temp_db("revote.db");
+        _vote(&db_path, "rust", true);
+        _vote(&db_path, "crypto", false);
+        let model = NaiveBayesClassifier::new(&db_path).unwrap();
+        assert!(holds_learned_votes(&db_path, model.trained_up_to, model.votes_seen()).unwrap());
+
+        _vote(&db_path, "crypto", true);
+        assert!(!holds_learned_votes(&db_path, model.trained_up_to, model.votes_seen()).unwrap());
+        let liked = votes_since(&db_path, 0)
+            .unwrap()
+            .into_iter()
+            .map(|(_, vote)| vote.liked)
+            .collect::<Vec<_>>();
+        assert_eq!(liked, [true, true]);
+
+        let db_path = _temp_db("revote.db");
+        _vote(&db_path, "rust", true);
+        assert!(!holds_learned_votes(&db_path, model.trained_up_to, model.votes_seen()).unwrap());
+        _remove_db(&db_path);
+    }
+
+    #[test]
+    fn test_fold_in_only_reads_new_votes() {
+        let db_path = _temp_db("fold-in.db");
+        _vote(&db_path, "rust async", true);
+        _vote(&db_path, "crypto deals", false);
+        let mut model = NaiveBayesClassifier::new(&db_path).unwrap();
+        assert_eq!(model.trained_up_to, 2);
+
+        _vote(&db_path, "rust", true);
+        let votes = votes_since(&db_path, model.trained_up_to).unwrap();
+        assert_eq!(votes.len(), 1);
+        model.fold_in(votes);
+        assert!(votes_since(&db_path, model.trained_up_to)
+            .unwrap()
+            .is_empty());
+        _remove_db(&db_path);
+
+        assert_eq!(model.liked_entries_count, 2);
+        assert_eq!(model.disliked_entries_count, 1);
+        assert_eq!(model.token_liked_counts["rust"], 2);
+        assert_eq!(model.trained_up_to, 3);
+    }
+
+    #[test]
+    fn test_saved_model_round_trips() {
+        let model_path = _temp_path("model.json");
+        let config = TokenizerConfig {
+            bigrams: true,
+            ..Default::default()
+        };
+        let mut model = NaiveBayesClassifier::with_tokenizer(0.5, config.build().unwrap());
+        for i in 0..=MIN_TRAINING_ENTRIES {
+            model.update(&_text_entry(&format!("rust w{}", i)), i % 3 == 0);
+        }
+        model.trained_up_to = 42;
+        model.save_model(Path::new(&model_path)).unwrap();
+        let loaded = NaiveBayesClassifier::load_model(Path::new(&model_path)).unwrap();
+        let _ = std::fs::remove_file(&model_path);
+
+        assert_eq!(loaded.alpha, 0.5);
+        assert_eq!(loaded.tokenizer().config(), &config);
+        assert_eq!(loaded.tokens, model.tokens);
+        assert_eq!(loaded.token_liked_counts, model.token_liked_counts);
+        assert_eq!(loaded.token_disliked_counts, model.token_disliked_counts);
+        assert_eq!(loaded.trained_up_to, 42);
+        assert!(loaded.is_prepared);
+        let entry = _text_entry("rust w3 w4");
+        assert!((loaded.classify(entry.clone()) - model.classify(entry)).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_model_counting_a_token_for_one_class_only_loads() {
+        let model = SavedModel {
+            format_version: MODEL_FORMAT_VERSION,
+            model: ModelKind::NaiveBayes,
+            tokenizer: TokenizerConfig::default(),
+            alpha: 1.,
+            liked_entries_count: 2,
+            disliked_entries_count: 1,
+            token_liked_counts: HashMap::from([("rust".to_string(), 2)]),
+            token_disliked_counts: HashMap::new(),
+            trained_up_to: 3,
+        };
+        let loaded =
+            NaiveBayesClassifier::from_saved(&serde_json::to_vec(&model).unwrap()).unwrap();
+
+        assert!(loaded.score_content(&_content("rust", false)) > 0.5);
+    }
+
+    #[test]
+    fn test_incompatible_models_are_rejected() {
+        let model_path = _temp_path("incompatible.json");
+        NaiveBayesClassifier::new_classifier(1.)
+            .save_model(Path::new(&model_path))
+            .unwrap();
+        let saved: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(&model_path).unwrap()).unwrap();
+
+        let mut newer = saved.clone();
+        newer["format_version"] = (MODEL_FORMAT_VERSION + 1).into();
+        std::fs::write(&model_path, newer.to_string()).unwrap();
+        assert!(NaiveBayesClassifier::load_model(Path::new(&model_path)).is_err());
+
+        let mut other_tokenizer = saved;
+        other_tokenizer["tokenizer"]["name"] = "whitespace".into();
+        std::fs::write(&model_path, other_tokenizer.to_string()).unwrap();
+        assert!(NaiveBayesClassifier::load_model(Path::new(&model_path)).is_err());
+        let _ = std::fs::remove_file(&model_path);
+    }
 
     #[test]
     fn naive_bayes() {
         let train_messages = [
             EntryContent {
-                all_content: "Free Bitcoin viagra XXX christmas deals 😻😻😻".to_string(),
+                text: "Free Bitcoin viagra XXX christmas deals 😻😻😻".to_string(),
                 liked: true,
+                ..Default::default()
             },
             EntryContent {
-                all_content: "My dear Granddaughter, please explain Bitcoin over Christmas dinner"
+                text: "My dear Granddaughter, please explain Bitcoin over Christmas dinner"
                     .to_string(),
                 liked: false,
+                ..Default::default()
             },
             EntryContent {
-                all_content: "Here in my garage...".to_string(),
+                text: "Here in my garage...".to_string(),
                 liked: true,
+                ..Default::default()
             },
         ];
 
@@ -236,10 +1028,11 @@ mod tests {
 
         let mut model = NaiveBayesClassifier::new_classifier(alpha);
         model.train(train_messages.to_vec());
+        model.is_prepared = true;
 
         let mut expected_tokens: HashSet<String> = HashSet::new();
         for message in train_messages.iter() {
-            for token in NaiveBayesClassifier::tokenize(&message.all_content.to_lowercase()) {
+            for token in model.tokenizer().tokenize(&message.text, None) {
                 expected_tokens.insert(token.to_string());
             }
         }
@@ -263,6 +1056,7 @@ mod tests {
             1. - (1. + alpha) / (num_spam_messages + 2. * alpha), // "here"  (not present)
             1. - (1. + alpha) / (num_spam_messages + 2. * alpha), // "in"  (not present)
             1. - (1. + alpha) / (num_spam_messages + 2. * alpha), // "garage"  (not present)
+            1. - (1. + alpha) / (num_spam_messages + 2. * alpha), // "😻"  (not present)
         ];
 
         let probs_if_ham = [
@@ -282,20 +1076,26 @@ mod tests {
             1. - (0. + alpha) / (num_ham_messages + 2. * alpha), // "here"  (not present)
             1. - (0. + alpha) / (num_ham_messages + 2. * alpha), // "in"  (not present)
             1. - (0. + alpha) / (num_ham_messages + 2. * alpha), // "garage"  (not present)
+            1. - (0. + alpha) / (num_ham_messages + 2. * alpha), // "😻"  (not present)
         ];
 
         let p_if_spam_log: f64 = probs_if_spam.iter().map(|p| p.ln()).sum();
-        let p_if_spam = p_if_spam_log.exp();
+        let p_if_spam = p_if_spam_log.exp() * num_spam_messages / 3.;
 
         let p_if_ham_log: f64 = probs_if_ham.iter().map(|p| p.ln()).sum();
-        let p_if_ham = p_if_ham_log.exp();
-        let mut entry = Entry::default();
-        entry.content = Some(Content {
-            body: Some(input_text.to_string()),
+        let p_if_ham = p_if_ham_log.exp() * num_ham_messages / 3.;
+        let entry = Entry {
+            content: Some(Content {
+                body: Some(input_text.to_string()),
+                ..Default::default()
+            }),
             ..Default::default()
-        });
-        // P(message | spam) / (P(messge | spam) + P(message | ham)) rounds to 0.97
+        };
+        // P(spam | message) = P(spam) P(message | spam) / P(message)
 
-        assert!((model.classify(entry) - p_if_spam / (p_if_spam + p_if_ham)).abs() < 0.034);
+        assert_eq!(model.liked_entries_count, 2);
+        assert_eq!(model.disliked_entries_count, 1);
+        assert_eq!(model.tokens, expected_tokens);
+        assert!((model.classify(entry) - p_if_spam / (p_if_spam + p_if_ham)).abs() < 1e-12);
     }
 }
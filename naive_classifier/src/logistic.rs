@@ -0,0 +1,241 @@
+//! Logistic regression over the same features as Naive Bayes, trained by
+//! stochastic gradient descent one vote at a time.
+
+use feed_rs::model::Entry;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::classifier::{self, Classifier, Explanation, ModelKind, MODEL_FORMAT_VERSION};
+use crate::tokenizer::{Tokenizer, TokenizerConfig};
+use crate::{EntryContent, MIN_TRAINING_ENTRIES};
+
+pub struct LogisticRegression {
+    pub learning_rate: f64,
+    /// L2 penalty, applied to the weights of the features of each example.
+    pub l2: f64,
+    /// Passes over the data made by `train`. `update` makes a single step.
+    pub epochs: usize,
+    pub weights: HashMap<String, f64>,
+    pub bias: f64,
+    pub examples_seen: i32,
+    pub is_prepared: bool,
+    pub trained_up_to: i64,
+    tokenizer: Box<dyn Tokenizer>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SavedModel {
+    format_version: u32,
+    model: ModelKind,
+    tokenizer: TokenizerConfig,
+    learning_rate: f64,
+    l2: f64,
+    epochs: usize,
+    weights: HashMap<String, f64>,
+    bias: f64,
+    examples_seen: i32,
+    trained_up_to: i64,
+}
+
+impl LogisticRegression {
+    pub fn new(tokenizer: Box<dyn Tokenizer>) -> Self {
+        LogisticRegression {
+            learning_rate: 0.1,
+            l2: 1e-4,
+            epochs: 5,
+            weights: HashMap::new(),
+            bias: 0.,
+            examples_seen: 0,
+            is_prepared: false,
+            trained_up_to: 0,
+            tokenizer,
+        }
+    }
+
+    pub(crate) fn from_saved(bytes: &[u8]) -> Result<Self, Box<dyn std::error::Error>> {
+        classifier::check_saved(bytes, ModelKind::LogisticRegression)?;
+        let model: SavedModel = serde_json::from_slice(bytes)?;
+        let mut regression = LogisticRegression::new(model.tokenizer.build()?);
+        regression.learning_rate = model.learning_rate;
+        regression.l2 = model.l2;
+        regression.epochs = model.epochs;
+        regression.weights = model.weights;
+        regression.bias = model.bias;
+        regression.examples_seen = model.examples_seen;
+        regression.is_prepared = model.examples_seen > MIN_TRAINING_ENTRIES;
+        regression.trained_up_to = model.trained_up_to;
+        Ok(regression)
+    }
+
+    fn log_odds(&self, features: &[(String, f64)]) -> f64 {
+        features
+            .iter()
+            .filter_map(|(feature, value)| Some(self.weights.get(feature)? * value))
+            .fold(self.bias, |sum, contribution| sum + contribution)
+    }
+
+    fn step(&mut self, features: &[(String, f64)], liked: bool) {
+        let predicted = 1. / (1. + (-self.log_odds(features)).exp());
+        let error = if liked { 1. } else { 0. } - predicted;
+        self.bias += self.learning_rate * error;
+        for (feature, value) in features {
+            let weight = self.weights.entry(feature.clone()).or_default();
+            *weight += self.learning_rate * (error * value - self.l2 * *weight);
+        }
+    }
+
+    /// The features of `entry`, each valued at the weight of its field.
+    fn features(&self, entry: &EntryContent) -> Vec<(String, f64)> {
+        let fields = &self.tokenizer.config().fields;
+        entry
+            .features(self.tokenizer.as_ref())
+            .into_iter()
+            .map(|feature| {
+                let value = fields.weight(&feature);
+                (feature, value)
+            })
+            .collect()
+    }
+}
+
+impl Classifier for LogisticRegression {
+    fn kind(&self) -> ModelKind {
+        ModelKind::LogisticRegression
+    }
+
+    fn train(&mut self, data: Vec<EntryContent>) {
+        let examples = data
+            .iter()
+            .map(|entry| (self.features(entry), entry.liked))
+            .collect::<Vec<_>>();
+        let epochs = if examples.len() > 1 { self.epochs } else { 1 };
+        for _ in 0..epochs {
+            for (features, liked) in &examples {
+                self.step(features, *liked);
+            }
+        }
+        self.examples_seen += examples.len() as i32;
+        self.is_prepared |= self.examples_seen > MIN_TRAINING_ENTRIES;
+    }
+
+    fn is_prepared(&self) -> bool {
+        self.is_prepared
+    }
+
+    fn votes_seen(&self) -> i32 {
+        self.examples_seen
+    }
+
+    fn score_content(&self, content: &EntryContent) -> f64 {
+        1. / (1. + (-self.log_odds(&self.features(content))).exp())
+    }
+
+    fn explain(&self, entry: &Entry) -> Explanation {
+        let contributions = self
+            .features(&EntryContent::from_entry(entry, false))
+            .into_iter()
+            .filter_map(|(feature, value)| {
+                let weight = *self.weights.get(&feature)?;
+                Some((feature, weight * value))
+            })
+            .collect();
+        Explanation::new(self.bias, contributions)
+    }
+
+    fn save(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        classifier::write_model(
+            path,
+            &SavedModel {
+                format_version: MODEL_FORMAT_VERSION,
+                model: ModelKind::LogisticRegression,
+                tokenizer: self.tokenizer.config().clone(),
+                learning_rate: self.learning_rate,
+                l2: self.l2,
+                epochs: self.epochs,
+                weights: self.weights.clone(),
+                bias: self.bias,
+                examples_seen: self.examples_seen,
+                trained_up_to: self.trained_up_to,
+            },
+        )
+    }
+
+    fn tokenizer(&self) -> &dyn Tokenizer {
+        self.tokenizer.as_ref()
+    }
+
+    fn trained_up_to(&self) -> i64 {
+        self.trained_up_to
+    }
+
+    fn set_trained_up_to(&mut self, rowid: i64) {
+        self.trained_up_to = rowid;
+    }
+
+    /// A model that already learned votes takes the ones it missed in a
+    /// single pass, like votes cast live, so they don't outweigh the rest.
+    fn fold_in(&mut self, votes: Vec<(i64, EntryContent)>) {
+        if self.examples_seen == 0 {
+            self.trained_up_to = votes.last().map_or(self.trained_up_to, |(last, _)| *last);
+            self.train(votes.into_iter().map(|(_, vote)| vote).collect());
+            return;
+        }
+        for (rowid, vote) in votes {
+            self.train(vec![vote]);
+            self.trained_up_to = rowid;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{_content, _text_entry};
+
+    #[test]
+    fn test_steps_move_weights_towards_the_vote() {
+        let mut model = LogisticRegression::new(TokenizerConfig::default().build().unwrap());
+        model.l2 = 0.;
+        model.update(&_text_entry("rust"), true);
+
+        // From all-zero weights the prediction is 0.5, so the error is 0.5.
+        assert_eq!(model.bias, 0.05);
+        assert_eq!(model.weights["rust"], 0.05);
+
+        model.update(&_text_entry("crypto"), false);
+        assert!(model.weights["crypto"] < 0.);
+        assert_eq!(model.weights["rust"], 0.05);
+        assert_eq!(model.examples_seen, 2);
+    }
+
+    #[test]
+    fn test_fold_in_steps_once_per_missed_vote() {
+        let votes = || vec![(1, _content("rust", true)), (2, _content("crypto", false))];
+        let mut saved = LogisticRegression::new(TokenizerConfig::default().build().unwrap());
+        saved.fold_in(votes());
+        let mut live = LogisticRegression::new(TokenizerConfig::default().build().unwrap());
+        live.train(votes().into_iter().map(|(_, vote)| vote).collect());
+        assert_eq!(saved.weights, live.weights);
+
+        saved.fold_in(votes());
+        for (_, vote) in votes() {
+            live.train(vec![vote]);
+        }
+        assert_eq!(saved.weights, live.weights);
+        assert_eq!(saved.examples_seen, 4);
+        assert_eq!(saved.trained_up_to, 2);
+    }
+
+    #[test]
+    fn test_unprepared_model_keeps_everything() {
+        let mut model = LogisticRegression::new(TokenizerConfig::default().build().unwrap());
+        for _ in 0..MIN_TRAINING_ENTRIES {
+            model.update(&_text_entry("crypto"), false);
+        }
+        assert_eq!(model.score(&_text_entry("crypto")), 1.);
+
+        model.update(&_text_entry("crypto"), false);
+        assert!(model.score(&_text_entry("crypto")) < 0.5);
+    }
+}
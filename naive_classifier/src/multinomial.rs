@@ -0,0 +1,542 @@
+//! Naive Bayes over how often each feature occurs, rather than whether it
+//! does: long content bodies weigh their words by count instead of counting
+//! like a title that mentions them once.
+
+use feed_rs::model::Entry;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::classifier::{self, Classifier, Explanation, ModelKind, MODEL_FORMAT_VERSION};
+use crate::tokenizer::{Tokenizer, TokenizerConfig};
+use crate::{EntryContent, MIN_TRAINING_ENTRIES};
+
+/// How an entry's feature counts are turned into the weights the model
+/// learns from, chosen with the `weighting` rocket config key.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Weighting {
+    /// The raw counts.
+    #[default]
+    Counts,
+    /// `ln(1 + count)` times the feature's inverse document frequency, so
+    /// repetition and features every entry has count for less.
+    TfIdf,
+    /// Counts divided by their euclidean norm, so every entry weighs the
+    /// same whatever its length.
+    LengthNormalized,
+}
+
+impl std::str::FromStr for Weighting {
+    type Err = String;
+
+    /// Parses the names used in the config, e.g. `tf-idf`.
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        serde_json::from_value(serde_json::Value::String(name.to_string()))
+            .map_err(|_| format!("unknown weighting {:?}", name))
+    }
+}
+
+/// Multinomial Naive Bayes or, with `complement`, Complement Naive Bayes
+/// (Rennie et al., 2003). That one estimates each class from the entries of
+/// every other class; with only liked and disliked, it comes down to this
+/// multinomial model without the priors and with each class's
+/// log-likelihoods normalized to a mean magnitude of 1, so a class with few
+/// votes isn't drowned out. Its scores order entries well but aren't
+/// calibrated probabilities.
+pub struct MultinomialNaiveBayes {
+    pub alpha: f64,
+    pub complement: bool,
+    pub weighting: Weighting,
+    /// Sum of each feature's weight over the liked entries, before the
+    /// inverse document frequency of `Weighting::TfIdf`.
+    pub token_liked_weights: HashMap<String, f64>,
+    pub token_disliked_weights: HashMap<String, f64>,
+    /// Entries each feature occurs in.
+    pub document_frequency: HashMap<String, i32>,
+    pub liked_entries_count: i32,
+    pub disliked_entries_count: i32,
+    pub is_prepared: bool,
+    pub trained_up_to: i64,
+    tokenizer: Box<dyn Tokenizer>,
+    compiled: CompiledModel,
+}
+
+/// What scoring needs beyond the sums of an entry's own features, kept up to
+/// date feature by feature so a vote doesn't go over the whole vocabulary.
+#[derive(Debug, Clone, Default)]
+struct CompiledModel {
+    baseline: f64,
+    liked: ClassSums,
+    disliked: ClassSums,
+}
+
+/// Sums over the vocabulary that make up one class's normalizer.
+#[derive(Debug, Clone, Default)]
+struct ClassSums {
+    /// Σ weight.
+    weight: f64,
+    /// Σ weight · ln(1 + document frequency): with `Weighting::TfIdf` the
+    /// weights scaled by their inverse document frequency add up to
+    /// (ln(1 + entries) + 1) · `weight` minus this.
+    weight_log_frequency: f64,
+    /// Σ ln(weight + alpha), for the mean magnitude of complement's
+    /// log-likelihoods.
+    log_weights: f64,
+    /// ln Σ (weight · idf + alpha), what ln P(feature | class) takes off.
+    log_total: f64,
+    /// What the log-likelihoods are multiplied by: 1, or one over their
+    /// mean magnitude for complement.
+    scale: f64,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SavedModel {
+    format_version: u32,
+    model: ModelKind,
+    tokenizer: TokenizerConfig,
+    alpha: f64,
+    weighting: Weighting,
+    token_liked_weights: HashMap<String, f64>,
+    token_disliked_weights: HashMap<String, f64>,
+    document_frequency: HashMap<String, i32>,
+    liked_entries_count: i32,
+    disliked_entries_count: i32,
+    trained_up_to: i64,
+}
+
+impl MultinomialNaiveBayes {
+    pub fn new(complement: bool, weighting: Weighting, tokenizer: Box<dyn Tokenizer>) -> Self {
+        MultinomialNaiveBayes {
+            alpha: 1.0,
+            complement,
+            weighting,
+            token_liked_weights: HashMap::new(),
+            token_disliked_weights: HashMap::new(),
+            document_frequency: HashMap::new(),
+            liked_entries_count: 0,
+            disliked_entries_count: 0,
+            is_prepared: false,
+            trained_up_to: 0,
+            tokenizer,
+            compiled: CompiledModel::default(),
+        }
+    }
+
+    pub(crate) fn from_saved(
+        bytes: &[u8],
+        kind: ModelKind,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        classifier::check_saved(bytes, kind)?;
+        let model: SavedModel = serde_json::from_slice(bytes)?;
+        let mut classifier = MultinomialNaiveBayes::new(
+            kind == ModelKind::ComplementNaiveBayes,
+            model.weighting,
+            model.tokenizer.build()?,
+        );
+        classifier.alpha = model.alpha;
+        classifier.token_liked_weights = model.token_liked_weights;
+        classifier.token_disliked_weights = model.token_disliked_weights;
+        classifier.document_frequency = model.document_frequency;
+        classifier.liked_entries_count = model.liked_entries_count;
+        classifier.disliked_entries_count = model.disliked_entries_count;
+        classifier.trained_up_to = model.trained_up_to;
+        classifier.compiled.liked = classifier.class_sums(&classifier.token_liked_weights);
+        classifier.compiled.disliked = classifier.class_sums(&classifier.token_disliked_weights);
+        classifier.counts_changed();
+        Ok(classifier)
+    }
+
+    fn counts_changed(&mut self) {
+        self.is_prepared |= self.votes_seen() > MIN_TRAINING_ENTRIES;
+        self.compile();
+    }
+
+    /// The weight of each feature of `content`, before inverse document
+    /// frequency. Counts are scaled by the weight of their field first.
+    fn weights(&self, content: &EntryContent) -> HashMap<String, f64> {
+        let fields = &self.tokenizer.config().fields;
+        let counts = content
+            .feature_counts(self.tokenizer.as_ref())
+            .into_iter()
+            .map(|(feature, count)| {
+                let count = count as f64 * fields.weight(&feature);
+                (feature, count)
+            })
+            .collect::<Vec<_>>();
+        let norm = counts
+            .iter()
+            .map(|(_, count)| count * count)
+            .sum::<f64>()
+            .sqrt();
+        counts
+            .into_iter()
+            .map(|(feature, count)| {
+                let weight = match self.weighting {
+                    Weighting::Counts => count,
+                    Weighting::TfIdf => count.ln_1p(),
+                    Weighting::LengthNormalized => count / norm,
+                };
+                (feature, weight)
+            })
+            .collect()
+    }
+
+    fn idf(&self, feature: &str) -> f64 {
+        if self.weighting != Weighting::TfIdf {
+            return 1.;
+        }
+        let entries = self.votes_seen() as f64;
+        let frequency = self.document_frequency.get(feature).copied().unwrap_or(0) as f64;
+        ((1. + entries) / (1. + frequency)).ln() + 1.
+    }
+
+    /// The [`ClassSums`] of a class's `sums`, worked out from scratch.
+    fn class_sums(&self, sums: &HashMap<String, f64>) -> ClassSums {
+        let log_frequency = |feature: &str| {
+            let frequency = self.document_frequency.get(feature).copied().unwrap_or(0);
+            (1. + frequency as f64).ln()
+        };
+        ClassSums {
+            weight: sums.values().sum(),
+            weight_log_frequency: sums
+                .iter()
+                .map(|(feature, weight)| weight * log_frequency(feature))
+                .sum(),
+            log_weights: self
+                .document_frequency
+                .keys()
+                .map(|feature| (sums.get(feature).copied().unwrap_or(0.) + self.alpha).ln())
+                .sum(),
+            ..Default::default()
+        }
+    }
+
+    /// Adds `weight` of `feature`, from an entry the user liked or not, to
+    /// the sums and to the [`ClassSums`] built on them.
+    fn learn(&mut self, feature: String, weight: f64, liked: bool) {
+        let alpha = self.alpha;
+        let frequency = self.document_frequency.entry(feature.clone()).or_default();
+        if *frequency == 0 {
+            self.compiled.liked.log_weights += alpha.ln();
+            self.compiled.disliked.log_weights += alpha.ln();
+        }
+        *frequency += 1;
+        let log_frequency = (1. + *frequency as f64).ln();
+        let frequency_change = log_frequency - (*frequency as f64).ln();
+        for (sums, class) in [
+            (&self.token_liked_weights, &mut self.compiled.liked),
+            (&self.token_disliked_weights, &mut self.compiled.disliked),
+        ] {
+            class.weight_log_frequency +=
+                sums.get(&feature).copied().unwrap_or(0.) * frequency_change;
+        }
+
+        let (sums, class) = if liked {
+            (&mut self.token_liked_weights, &mut self.compiled.liked)
+        } else {
+            (
+                &mut self.token_disliked_weights,
+                &mut self.compiled.disliked,
+            )
+        };
+        let sum = sums.entry(feature).or_default();
+        class.weight += weight;
+        class.weight_log_frequency += weight * log_frequency;
+        class.log_weights += (*sum + weight + alpha).ln() - (*sum + alpha).ln();
+        *sum += weight;
+    }
+
+    fn compile(&mut self) {
+        if self.document_frequency.is_empty() {
+            self.compiled.baseline = 0.;
+            return;
+        }
+        let (log_total, scale) = self.normalizer(&self.token_liked_weights, &self.compiled.liked);
+        (self.compiled.liked.log_total, self.compiled.liked.scale) = (log_total, scale);
+        let (log_total, scale) =
+            self.normalizer(&self.token_disliked_weights, &self.compiled.disliked);
+        (
+            self.compiled.disliked.log_total,
+            self.compiled.disliked.scale,
+        ) = (log_total, scale);
+
+        self.compiled.baseline = if self.complement {
+            0.
+        } else {
+            let epsilon = 1e-9;
+            let total = self.votes_seen() as f64;
+            (self.liked_entries_count as f64 / total).max(epsilon).ln()
+                - (self.disliked_entries_count as f64 / total)
+                    .max(epsilon)
+                    .ln()
+        };
+    }
+
+    /// [`ClassSums::log_total`] and [`ClassSums::scale`] of a class.
+    fn normalizer(&self, sums: &HashMap<String, f64>, class: &ClassSums) -> (f64, f64) {
+        let vocabulary = self.document_frequency.len() as f64;
+        let weight = match self.weighting {
+            Weighting::TfIdf => {
+                ((1. + self.votes_seen() as f64).ln() + 1.) * class.weight
+                    - class.weight_log_frequency
+            }
+            _ => class.weight,
+        };
+        let log_total = (weight + self.alpha * vocabulary).ln();
+        if !self.complement {
+            return (log_total, 1.);
+        }
+        // every feature's inverse document frequency moves with each entry,
+        // so tf-idf has to sum its log-weights again
+        let log_weights = match self.weighting {
+            Weighting::TfIdf => self
+                .document_frequency
+                .keys()
+                .map(|feature| {
+                    let weight = sums.get(feature).copied().unwrap_or(0.);
+                    (weight * self.idf(feature) + self.alpha).ln()
+                })
+                .sum(),
+            _ => class.log_weights,
+        };
+        // liked is predicted when the liked entries explain the features
+        // better than the disliked ones, each class's log-likelihoods, all
+        // negative, scaled to a mean magnitude of 1 so neither dominates by
+        // its size
+        (
+            log_total,
+            vocabulary / (vocabulary * log_total - log_weights),
+        )
+    }
+
+    /// What one unit of `feature`'s weight adds to the log-odds, inverse
+    /// document frequency included, `None` for a feature never seen in
+    /// training.
+    fn log_odds(&self, feature: &str) -> Option<f64> {
+        if !self.document_frequency.contains_key(feature) {
+            return None;
+        }
+        let idf = self.idf(feature);
+        // ln P(feature | class), smoothed by `alpha`
+        let log_likelihood = |sums: &HashMap<String, f64>, class: &ClassSums| {
+            let weight = sums.get(feature).copied().unwrap_or(0.) * idf;
+            ((weight + self.alpha).ln() - class.log_total) * class.scale
+        };
+        Some(
+            idf * (log_likelihood(&self.token_liked_weights, &self.compiled.liked)
+                - log_likelihood(&self.token_disliked_weights, &self.compiled.disliked)),
+        )
+    }
+
+    /// Each known feature of `content` with what it adds to the log-odds.
+    fn contributions(&self, content: &EntryContent) -> Vec<(String, f64)> {
+        self.weights(content)
+            .into_iter()
+            .filter_map(|(feature, weight)| {
+                let log_odds = self.log_odds(&feature)?;
+                Some((feature, weight * log_odds))
+            })
+            .collect()
+    }
+}
+
+impl Classifier for MultinomialNaiveBayes {
+    fn kind(&self) -> ModelKind {
+        if self.complement {
+            ModelKind::ComplementNaiveBayes
+        } else {
+            ModelKind::MultinomialNaiveBayes
+        }
+    }
+
+    fn train(&mut self, data: Vec<EntryContent>) {
+        for entry in &data {
+            if entry.liked {
+                self.liked_entries_count += 1;
+            } else {
+                self.disliked_entries_count += 1;
+            }
+            for (feature, weight) in self.weights(entry) {
+                self.learn(feature, weight, entry.liked);
+            }
+        }
+        self.counts_changed();
+    }
+
+    fn is_prepared(&self) -> bool {
+        self.is_prepared
+    }
+
+    fn votes_seen(&self) -> i32 {
+        self.liked_entries_count + self.disliked_entries_count
+    }
+
+    fn weighting(&self) -> Option<Weighting> {
+        Some(self.weighting)
+    }
+
+    fn score_content(&self, content: &EntryContent) -> f64 {
+        let log_odds = self
+            .contributions(content)
+            .iter()
+            .fold(self.compiled.baseline, |sum, (_, c)| sum + c);
+        1. / (1. + (-log_odds).exp())
+    }
+
+    fn explain(&self, entry: &Entry) -> Explanation {
+        let contributions = self.contributions(&EntryContent::from_entry(entry, false));
+        Explanation::new(self.compiled.baseline, contributions)
+    }
+
+    fn save(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        classifier::write_model(
+            path,
+            &SavedModel {
+                format_version: MODEL_FORMAT_VERSION,
+                model: self.kind(),
+                tokenizer: self.tokenizer.config().clone(),
+                alpha: self.alpha,
+                weighting: self.weighting,
+                token_liked_weights: self.token_liked_weights.clone(),
+                token_disliked_weights: self.token_disliked_weights.clone(),
+                document_frequency: self.document_frequency.clone(),
+                liked_entries_count: self.liked_entries_count,
+                disliked_entries_count: self.disliked_entries_count,
+                trained_up_to: self.trained_up_to,
+            },
+        )
+    }
+
+    fn tokenizer(&self) -> &dyn Tokenizer {
+        self.tokenizer.as_ref()
+    }
+
+    fn trained_up_to(&self) -> i64 {
+        self.trained_up_to
+    }
+
+    fn set_trained_up_to(&mut self, rowid: i64) {
+        self.trained_up_to = rowid;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::_content;
+    use crate::tokenizer::TokenizerConfig;
+
+    fn _model(complement: bool, weighting: Weighting) -> MultinomialNaiveBayes {
+        MultinomialNaiveBayes::new(
+            complement,
+            weighting,
+            TokenizerConfig::default().build().unwrap(),
+        )
+    }
+
+    fn _votes(text: &str, liked: bool, n: usize) -> Vec<EntryContent> {
+        vec![_content(text, liked); n]
+    }
+
+    #[test]
+    fn test_posterior_of_worked_example() {
+        let mut model = _model(false, Weighting::Counts);
+        model.train(vec![_content("a a b", true), _content("b c", false)]);
+
+        // P(a|liked) = (2 + 1) / (3 + 3) against P(a|disliked) = (0 + 1) / (2 + 3)
+        let score = model.score_content(&_content("a", false));
+        assert!((score - 5. / 7.).abs() < 1e-12);
+        // odds (5/2)^2 * (1/6) / (2/5): the count of a matters
+        let odds = 6.25 * 5. / 12.;
+        let score = model.score_content(&_content("a a c", false));
+        assert!((score - odds / (1. + odds)).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_complement_ignores_unbalanced_priors() {
+        let mut votes = _votes("rust tokio", true, 5);
+        votes.extend(_votes("crypto tokio", false, 95));
+        let mut multinomial = _model(false, Weighting::Counts);
+        multinomial.train(votes.clone());
+        let mut complement = _model(true, Weighting::Counts);
+        complement.train(votes);
+
+        let tokio = _content("tokio", false);
+        assert!(multinomial.score_content(&tokio) < 0.1);
+        assert!(complement.score_content(&tokio) > 0.4);
+        assert_eq!(complement.compiled.baseline, 0.);
+        assert!(complement.score_content(&_content("rust", false)) > 0.5);
+        assert!(complement.score_content(&_content("crypto", false)) < 0.5);
+    }
+
+    #[test]
+    fn test_weightings() {
+        let mut tf_idf = _model(false, Weighting::TfIdf);
+        tf_idf.train(_votes("news rust", true, 3));
+        tf_idf.train(_votes("news crypto", false, 3));
+        let weights = tf_idf.weights(&_content("rust rust rust", false));
+
+        assert_eq!(weights["rust"], 4f64.ln());
+        assert_eq!(tf_idf.idf("news"), 1.);
+        assert!((tf_idf.idf("rust") - ((7. / 4f64).ln() + 1.)).abs() < 1e-12);
+
+        let normalized = _model(false, Weighting::LengthNormalized);
+        let weights = normalized.weights(&_content("a a b", false));
+        assert!((weights["a"] - 2. / 5f64.sqrt()).abs() < 1e-12);
+        assert!((weights["b"] - 1. / 5f64.sqrt()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_votes_one_at_a_time_match_a_model_built_from_the_sums() {
+        let votes = [
+            ("rust rust async", true),
+            ("crypto deals deals", false),
+            ("rust news", true),
+            ("news crypto", false),
+        ];
+        for complement in [false, true] {
+            for weighting in [
+                Weighting::Counts,
+                Weighting::TfIdf,
+                Weighting::LengthNormalized,
+            ] {
+                let mut model = _model(complement, weighting);
+                for (text, liked) in votes {
+                    model.train(vec![_content(text, liked)]);
+                }
+                let path = std::env::temp_dir().join(format!(
+                    "{}-{}-{:?}.json",
+                    std::process::id(),
+                    complement,
+                    weighting
+                ));
+                model.save(&path).unwrap();
+                let bytes = std::fs::read(&path).unwrap();
+                let _ = std::fs::remove_file(&path);
+                let rebuilt = MultinomialNaiveBayes::from_saved(&bytes, model.kind()).unwrap();
+
+                for text in ["rust", "crypto news", "async deals rust"] {
+                    let content = _content(text, false);
+                    let score = model.score_content(&content);
+                    assert!((score - rebuilt.score_content(&content)).abs() < 1e-12);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_weighting_is_saved() {
+        let path = std::env::temp_dir().join(format!("{}-weighting.json", std::process::id()));
+        let mut model = _model(true, Weighting::TfIdf);
+        model.train(_votes("rust", true, 2));
+        model.save(&path).unwrap();
+        let loaded = classifier::load(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+
+        assert_eq!(loaded.kind(), ModelKind::ComplementNaiveBayes);
+        assert_eq!(loaded.weighting(), Some(Weighting::TfIdf));
+        assert_eq!(loaded.votes_seen(), 2);
+        assert_eq!("length-normalized".parse(), Ok(Weighting::LengthNormalized));
+    }
+}
@@ -0,0 +1,170 @@
+//! Features of how an entry is put together rather than what it says: spam
+//! tends to be long or empty, stuffed with links, shouting, anonymous. Each
+//! measure is cut into a few buckets and becomes a `structure:` feature, so
+//! every model learns and explains them like words.
+
+use crate::EntryContent;
+
+/// Times the title must occur in the summary and content to count as
+/// repeated: once or twice is just a summary that starts with it.
+const TITLE_REPEATS: usize = 3;
+
+/// The `structure:` features of `content`, e.g. `structure:links-5-9` or
+/// `structure:no-author`.
+pub(crate) fn features(content: &EntryContent) -> Vec<String> {
+    let html = content.text.to_lowercase();
+    let text = strip_tags(&content.text);
+
+    let mut features = vec![
+        format!("length-{}", length_bucket(text.chars().count())),
+        format!("links-{}", count_bucket(tags(&html, "a"))),
+        format!("images-{}", count_bucket(tags(&html, "img"))),
+        format!("enclosures-{}", count_bucket(content.enclosures)),
+    ];
+    if let Some(share) = uppercase_share(&format!("{} {}", content.title, text)) {
+        features.push(format!("caps-{}", caps_bucket(share)));
+    }
+    if content
+        .authors
+        .iter()
+        .all(|author| author.trim().is_empty())
+    {
+        features.push("no-author".to_string());
+    }
+    let title = content.title.trim().to_lowercase();
+    if !title.is_empty() && text.to_lowercase().matches(&title).count() >= TITLE_REPEATS {
+        features.push("repeated-title".to_string());
+    }
+    features
+        .into_iter()
+        .map(|feature| format!("structure:{}", feature))
+        .collect()
+}
+
+/// `html` without its tags, good enough to measure the text.
+fn strip_tags(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut in_tag = false;
+    for c in html.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => in_tag = false,
+            c if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+    text
+}
+
+/// Opening `<name>` tags in lowercased `html`.
+fn tags(html: &str, name: &str) -> usize {
+    html.match_indices('<')
+        .filter(|(i, _)| {
+            let rest = &html[i + 1..];
+            rest.strip_prefix(name)
+                .is_some_and(|after| after.starts_with(|c: char| c == '>' || c.is_whitespace()))
+        })
+        .count()
+}
+
+/// Share of the letters of `text` that are uppercase, `None` without letters.
+fn uppercase_share(text: &str) -> Option<f64> {
+    let (letters, upper) = text
+        .chars()
+        .filter(|c| c.is_alphabetic())
+        .fold((0, 0), |(letters, upper), c| {
+            (letters + 1, upper + c.is_uppercase() as usize)
+        });
+    (letters > 0).then(|| upper as f64 / letters as f64)
+}
+
+fn length_bucket(chars: usize) -> &'static str {
+    match chars {
+        0 => "empty",
+        1..200 => "short",
+        200..1000 => "medium",
+        1000..5000 => "long",
+        _ => "very-long",
+    }
+}
+
+fn count_bucket(count: usize) -> &'static str {
+    match count {
+        0 => "0",
+        1 => "1",
+        2..5 => "2-4",
+        5..10 => "5-9",
+        _ => "10+",
+    }
+}
+
+fn caps_bucket(share: f64) -> &'static str {
+    if share < 0.1 {
+        "low"
+    } else if share < 0.3 {
+        "some"
+    } else {
+        "high"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn _content(title: &str, text: &str) -> EntryContent {
+        EntryContent {
+            title: title.to_string(),
+            text: text.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_spam_and_a_plain_post_differ() {
+        let links = "<a href=\"x\">FREE</a> ".repeat(6);
+        let spam = _content(
+            "FREE CRYPTO",
+            &format!("FREE CRYPTO {0} FREE CRYPTO FREE CRYPTO {0}", links),
+        );
+        let mut post = _content(
+            "Rust 1.76 released",
+            "<p>The Rust team is happy to announce <img src=\"logo.png\"> a new version</p>",
+        );
+        post.authors = vec!["Jane".to_string()];
+        post.enclosures = 1;
+
+        assert_eq!(
+            features(&spam),
+            [
+                "structure:length-short",
+                "structure:links-10+",
+                "structure:images-0",
+                "structure:enclosures-0",
+                "structure:caps-high",
+                "structure:no-author",
+                "structure:repeated-title",
+            ]
+        );
+        assert_eq!(
+            features(&post),
+            [
+                "structure:length-short",
+                "structure:links-0",
+                "structure:images-1",
+                "structure:enclosures-1",
+                "structure:caps-low",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_measures() {
+        assert_eq!(strip_tags("<p>a <b>b</b></p>"), "a b");
+        assert_eq!(tags("<a href=x><abbr>a</abbr><a>", "a"), 2);
+        assert_eq!(uppercase_share("ABcd 12"), Some(0.5));
+        assert_eq!(uppercase_share("12"), None);
+        assert_eq!(length_bucket(1000), "long");
+        assert_eq!(count_bucket(4), "2-4");
+    }
+}
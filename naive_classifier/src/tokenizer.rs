@@ -0,0 +1,336 @@
+//! Turning entry text into the tokens the classifier counts.
+
+use regex::Regex;
+use rust_stemmers::{Algorithm, Stemmer};
+use serde::{Deserialize, Serialize};
+use std::sync::OnceLock;
+
+/// Splits text into tokens. Training and classifying must go through the
+/// same tokenizer, so its [`TokenizerConfig`] is stored with saved models.
+pub trait Tokenizer: Send + Sync {
+    /// Tokens of `text`, `language` being the entry's language tag (e.g.
+    /// `"en-US"`) when the feed declares one.
+    fn tokenize(&self, text: &str, language: Option<&str>) -> Vec<String>;
+
+    fn config(&self) -> &TokenizerConfig;
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TokenizerConfig {
+    /// Which [`Tokenizer`] these settings are for.
+    pub name: String,
+    /// Drops the most common words of the entry's language.
+    pub stopwords: bool,
+    /// Reduces words of the entry's language to their Snowball stem, so
+    /// "release", "released" and "releases" count as one token.
+    pub stemming: bool,
+    /// Adds every pair of consecutive words as a token of its own.
+    pub bigrams: bool,
+    /// Language assumed for entries that don't declare one.
+    pub default_language: Option<String>,
+    /// Adds features of the entry's shape, see [`crate::structure`].
+    pub structure: bool,
+    /// How much the features of each field of an entry count.
+    pub fields: FieldWeights,
+}
+
+impl Default for TokenizerConfig {
+    fn default() -> Self {
+        TokenizerConfig {
+            name: UnicodeTokenizer::NAME.to_string(),
+            stopwords: false,
+            stemming: false,
+            bigrams: false,
+            default_language: None,
+            structure: false,
+            fields: FieldWeights::default(),
+        }
+    }
+}
+
+impl TokenizerConfig {
+    /// The tokenizer these settings describe, if this build knows it.
+    pub fn build(&self) -> Result<Box<dyn Tokenizer>, Box<dyn std::error::Error>> {
+        match self.name.as_str() {
+            UnicodeTokenizer::NAME => Ok(Box::new(UnicodeTokenizer::new(self.clone()))),
+            name => Err(format!("unknown tokenizer {:?}", name).into()),
+        }
+    }
+}
+
+/// Multiplies what a feature adds to a score, by the field it comes from,
+/// e.g. so the title counts for more than the body. All 1 by default.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FieldWeights {
+    /// Words of the title, `title:` features.
+    pub title: f64,
+    /// Words of the summary and content, which have no prefix.
+    pub text: f64,
+    /// Author names, `author:` features.
+    pub author: f64,
+    /// Category terms, `category:` features.
+    pub category: f64,
+    /// The feed the entry came from, a `feed:` feature.
+    pub feed: f64,
+    /// Hosts the entry links to, `domain:` features.
+    pub domain: f64,
+    /// Words of the paths it links to, `path:` features.
+    pub path: f64,
+    /// How the entry is put together, `structure:` features.
+    pub structure: f64,
+}
+
+impl Default for FieldWeights {
+    fn default() -> Self {
+        FieldWeights {
+            title: 1.,
+            text: 1.,
+            author: 1.,
+            category: 1.,
+            feed: 1.,
+            domain: 1.,
+            path: 1.,
+            structure: 1.,
+        }
+    }
+}
+
+impl FieldWeights {
+    /// The weight of the field `feature` comes from, known by its prefix.
+    pub fn weight(&self, feature: &str) -> f64 {
+        match feature.split_once(':').map(|(field, _)| field) {
+            Some("title") => self.title,
+            Some("author") => self.author,
+            Some("category") => self.category,
+            Some("feed") => self.feed,
+            Some("domain") => self.domain,
+            Some("path") => self.path,
+            Some("structure") => self.structure,
+            _ => self.text,
+        }
+    }
+}
+
+/// Lowercased words as Unicode sees them: runs of letters, marks and digits
+/// (apostrophes allowed inside), with every Han character and every emoji a
+/// token on its own, and kana runs kept together.
+#[derive(Debug, Clone, Default)]
+pub struct UnicodeTokenizer {
+    config: TokenizerConfig,
+}
+
+impl UnicodeTokenizer {
+    pub const NAME: &'static str = "unicode-words";
+
+    pub fn new(config: TokenizerConfig) -> Self {
+        UnicodeTokenizer { config }
+    }
+}
+
+impl Tokenizer for UnicodeTokenizer {
+    fn tokenize(&self, text: &str, language: Option<&str>) -> Vec<String> {
+        let lower_case_text = text.to_lowercase();
+        let language = language
+            .or(self.config.default_language.as_deref())
+            .map(primary_subtag)
+            .unwrap_or_default();
+        let stopwords = if self.config.stopwords {
+            stopwords(&language)
+        } else {
+            &[]
+        };
+
+        let words = words(&lower_case_text)
+            .filter(|word| !stopwords.contains(word))
+            .map(|word| {
+                if self.config.stemming {
+                    stem(word, &language)
+                } else {
+                    word.to_string()
+                }
+            })
+            .collect::<Vec<_>>();
+
+        if !self.config.bigrams {
+            return words;
+        }
+        let bigrams = words
+            .windows(2)
+            .map(|pair| format!("{} {}", pair[0], pair[1]))
+            .collect::<Vec<_>>();
+        words.into_iter().chain(bigrams).collect()
+    }
+
+    fn config(&self) -> &TokenizerConfig {
+        &self.config
+    }
+}
+
+/// The words of `text`, without any language-specific processing.
+pub fn words(text: &str) -> impl Iterator<Item = &str> {
+    static WORD: OnceLock<Regex> = OnceLock::new();
+    WORD.get_or_init(|| {
+        Regex::new(
+            r"\p{Han}|[\p{Hiragana}ー]+|[\p{Katakana}ー]+|\p{Extended_Pictographic}|[[\p{Alphabetic}\p{M}\p{Nd}]--[\p{Han}\p{Hiragana}\p{Katakana}ー]]+(?:['’][[\p{Alphabetic}\p{M}\p{Nd}]--[\p{Han}\p{Hiragana}\p{Katakana}ー]]+)*",
+        )
+        .unwrap()
+    })
+    .find_iter(text)
+    .map(|mat| mat.as_str())
+}
+
+/// `"pt-BR"` into `"pt"`.
+fn primary_subtag(language: &str) -> String {
+    language
+        .split(['-', '_'])
+        .next()
+        .unwrap_or_default()
+        .to_lowercase()
+}
+
+fn stopwords(language: &str) -> &'static [&'static str] {
+    match language {
+        "en" => &[
+            "a", "about", "an", "and", "are", "as", "at", "be", "but", "by", "for", "from", "has",
+            "have", "he", "her", "his", "i", "in", "is", "it", "its", "my", "not", "of", "on",
+            "or", "our", "she", "that", "the", "their", "they", "this", "to", "was", "we", "were",
+            "will", "with", "you", "your",
+        ],
+        "pt" => &[
+            "a", "ao", "as", "com", "como", "da", "das", "de", "do", "dos", "e", "é", "em", "era",
+            "essa", "esse", "eu", "foi", "isso", "mais", "mas", "na", "nas", "no", "nos", "não",
+            "o", "os", "ou", "para", "pela", "pelo", "por", "que", "se", "sem", "seu", "sua", "um",
+            "uma",
+        ],
+        "es" => &[
+            "a", "al", "como", "con", "de", "del", "el", "en", "es", "esta", "este", "la", "las",
+            "lo", "los", "más", "no", "o", "para", "pero", "por", "que", "se", "sin", "su", "sus",
+            "un", "una", "y",
+        ],
+        "de" => &[
+            "auf", "aus", "bei", "das", "dass", "dem", "den", "der", "des", "die", "ein", "eine",
+            "einen", "einer", "es", "für", "ich", "ist", "im", "in", "mit", "nicht", "sich", "sie",
+            "so", "und", "von", "wir", "zu", "zum", "zur",
+        ],
+        "fr" => &[
+            "au", "aux", "avec", "ce", "ces", "dans", "de", "des", "du", "en", "est", "et", "il",
+            "je", "la", "le", "les", "leur", "mais", "ne", "nous", "on", "ou", "par", "pas",
+            "pour", "qui", "que", "sa", "se", "son", "sur", "un", "une", "vous",
+        ],
+        _ => &[],
+    }
+}
+
+/// The Snowball stem of `word`, left alone in languages without a stemmer.
+fn stem(word: &str, language: &str) -> String {
+    let algorithm = match language {
+        "en" => Algorithm::English,
+        "pt" => Algorithm::Portuguese,
+        "es" => Algorithm::Spanish,
+        "de" => Algorithm::German,
+        "fr" => Algorithm::French,
+        _ => return word.to_string(),
+    };
+    Stemmer::create(algorithm).stem(word).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_words_keep_non_latin_scripts() {
+        let words = words("Ação rápida, Straße & naïve don't 東京タワー 😻😻").collect::<Vec<_>>();
+
+        assert_eq!(
+            words,
+            [
+                "Ação",
+                "rápida",
+                "Straße",
+                "naïve",
+                "don't",
+                "東",
+                "京",
+                "タワー",
+                "😻",
+                "😻"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_stopwords_and_stemming_follow_the_language() {
+        let tokenizer = UnicodeTokenizer::new(TokenizerConfig {
+            stopwords: true,
+            stemming: true,
+            ..Default::default()
+        });
+
+        assert_eq!(
+            tokenizer.tokenize("The releases of the libraries", Some("en-US")),
+            ["releas", "librari"]
+        );
+        assert_eq!(
+            tokenizer.tokenize("Os livros do Brasil", Some("pt-BR")),
+            ["livr", "brasil"]
+        );
+        assert_eq!(
+            tokenizer.tokenize("release released releases", Some("en")),
+            ["releas", "releas", "releas"]
+        );
+        assert_eq!(
+            tokenizer.tokenize("The releases", None),
+            ["the", "releases"]
+        );
+    }
+
+    #[test]
+    fn test_stemming_keeps_unrelated_words_apart() {
+        let stems = |text: &str| words(text).map(|word| stem(word, "en")).collect::<Vec<_>>();
+
+        assert_eq!(
+            stems("apply app spring spree"),
+            ["appli", "app", "spring", "spree"]
+        );
+        assert_eq!(stems("connect connected connecting"), ["connect"; 3]);
+    }
+
+    #[test]
+    fn test_default_language_and_bigrams() {
+        let tokenizer = UnicodeTokenizer::new(TokenizerConfig {
+            stopwords: true,
+            bigrams: true,
+            default_language: Some("en".to_string()),
+            ..Default::default()
+        });
+
+        assert_eq!(
+            tokenizer.tokenize("Rust is a systems language", None),
+            [
+                "rust",
+                "systems",
+                "language",
+                "rust systems",
+                "systems language"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_config_builds_its_tokenizer() {
+        let config = TokenizerConfig {
+            bigrams: true,
+            ..Default::default()
+        };
+        assert_eq!(config.build().unwrap().config(), &config);
+
+        let unknown = TokenizerConfig {
+            name: "whitespace".to_string(),
+            ..Default::default()
+        };
+        assert!(unknown.build().is_err());
+    }
+}
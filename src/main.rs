@@ -1,7 +1,23 @@
 #[macro_use]
 extern crate rocket;
-use feed_sync::{parser::Parser, FeedManager};
-use naive_classifier::NaiveBayesClassifier;
+use feed_rs::model::Entry;
+use feed_sync::{
+    fetch_feeds,
+    parser::{Parser, Reasons},
+    ranking::Order,
+    scheduler::Scheduler,
+    scoring::{ScoreHook, Scorer},
+    FeedManager, QueuedEntry,
+};
+use naive_classifier::{
+    active::{self, TrainingLog},
+    classifier::{self, Classifier, Explanation, ModelKind},
+    exploration::Exploration,
+    holds_learned_votes,
+    multinomial::Weighting,
+    tokenizer::{Tokenizer, TokenizerConfig},
+    votes_since,
+};
 
 use rocket::http::Status;
 use rocket::response::status::Custom;
@@ -14,59 +30,304 @@ use rocket::{
 };
 
 use std::{
+    collections::HashMap,
     path::{Path, PathBuf},
-    sync::{Arc, Mutex},
+    sync::{Arc, Mutex, MutexGuard},
+    time::{Duration, Instant},
 };
+use tokio::sync::Notify;
+
+/// Refresh interval, in seconds, for feeds without a `ttl` or an override.
+/// Can be changed with the `sync_interval` rocket config key.
+const DEFAULT_SYNC_INTERVAL: u64 = 30 * 60;
+const SYNC_TICK: Duration = Duration::from_secs(30);
+const DEFAULT_MODEL_PATH: &str = "db/model.json";
+/// Score from which entries are shown.
+const THRESHOLD: f64 = 0.5;
+/// Entries scored per lock of the classifier by [`rescore_on_change`].
+const RESCORE_BATCH: usize = 100;
+/// Queued entries, next to be shown first, a training-mode vote is measured on.
+const SHIFT_SAMPLE: usize = 200;
+/// Tokens listed each way when explaining a score.
+const EXPLAINED_TOKENS: usize = 5;
 
 #[derive(Serialize, Deserialize)]
 struct IsLiked {
     liked: Option<bool>,
+    /// The entry voted on, as given by the `data-feed-url` and `data-id` of
+    /// what the last `/next` showed.
+    feed_url: Option<String>,
+    id: Option<String>,
 }
 
-#[post("/next", data = "<msg>")]
-fn next(state: &StateApp, msg: Json<IsLiked>) -> RawHtml<String> {
-    let mut manager = state.manager.lock().unwrap();
-
+/// Records the vote on the entry shown last, if any, and shows the next one
+/// by `order` (see [`Order`], the queue's own order by default).
+#[post("/next?<order>", data = "<msg>")]
+fn next(
+    state: &StateApp,
+    msg: Json<IsLiked>,
+    order: Option<&str>,
+) -> Result<RawHtml<String>, Custom<String>> {
+    let order = order
+        .map(str::parse::<Order>)
+        .transpose()
+        .map_err(|e| Custom(Status::BadRequest, e))?
+        .unwrap_or_default();
     if let Some(is_liked) = msg.liked {
-        let last = manager.to_see.pop().unwrap().clone();
-        manager.already_seen.push((last, is_liked));
+        let (Some(feed_url), Some(id)) = (&msg.feed_url, &msg.id) else {
+            return Err(Custom(
+                Status::BadRequest,
+                "a vote needs the feed_url and id of the entry shown".to_string(),
+            ));
+        };
+        let mut manager = state.manager.lock().unwrap();
+        // syncs append to the queue, so the entry shown may no longer be last
+        if let Some(shown) = manager.take_queued(feed_url, id) {
+            record_vote(state, manager, shown, is_liked, order == Order::Training);
+        }
     }
 
+    let mut manager = state.manager.lock().unwrap();
+    // ranking only reads stored scores: the entries not scored yet wait for
+    // `rescore_on_change` as if they were liked, rather than being scored
+    // here while syncs and votes wait on both locks
+    manager.rank(order, |queued| queued.score.map_or(1., |score| score.value));
+
     loop {
         let current = manager.to_see.last();
         if current.is_none() {
-            return RawHtml("No more entries".to_string());
+            return Ok(RawHtml("No more entries".to_string()));
         }
         let current = current.unwrap();
-        let possibility_of_like = state.classifier.lock().unwrap().classify(current.clone());
-        if possibility_of_like >= 0.5 {
-            if manager.already_seen.contains(&(current.clone(), true))
-                || manager.already_seen.contains(&(current.clone(), false))
-            {
-                manager.to_see.pop();
-                continue;
-            }
-            return RawHtml(current.into_html());
+        let classifier = state.classifier.lock().unwrap();
+        let possibility_of_like = if classifier.is_prepared() {
+            stored_probability(classifier.as_ref(), current)
         } else {
-            manager.to_see.pop();
+            1.
+        };
+        let exploratory = possibility_of_like < THRESHOLD
+            && state
+                .exploration
+                .explores(possibility_of_like, THRESHOLD, &mut rand::thread_rng());
+        // training mode is about voting on borderline entries, hidden or not
+        if possibility_of_like >= THRESHOLD || exploratory || order == Order::Training {
+            let reasons = classifier.is_prepared().then(|| {
+                let explanation = classifier.explain(&current.entry);
+                Reasons {
+                    score: possibility_of_like,
+                    toward_like: explanation.toward_like(EXPLAINED_TOKENS),
+                    toward_dislike: explanation.toward_dislike(EXPLAINED_TOKENS),
+                    exploratory,
+                }
+            });
+            return Ok(RawHtml(format!(
+                "<article data-feed-url=\"{}\" data-id=\"{}\">{}</article>",
+                attribute(&current.feed_url),
+                attribute(&current.entry.id),
+                current.entry.into_html_with_reasons(reasons.as_ref())
+            )));
+        } else {
+            manager.filter_last(possibility_of_like);
         }
     }
 }
 
+/// `text` escaped to go between the double quotes of an HTML attribute.
+fn attribute(text: &str) -> String {
+    text.replace('&', "&amp;").replace('"', "&quot;")
+}
+
+/// The score stored with `queued`, even when votes since made it stale
+/// until [`rescore_on_change`] publishes new ones, else a fresh one.
+fn stored_probability(classifier: &dyn Classifier, queued: &QueuedEntry) -> f64 {
+    match queued.score {
+        Some(score) => score.value,
+        None => classifier.probability(&queued.entry),
+    }
+}
+
+/// Scores a queued entry the moment it is fetched.
+struct ClassifierScorer(Arc<Mutex<Box<dyn Classifier>>>);
+
+impl Scorer for ClassifierScorer {
+    fn score(&self, entry: &Entry) -> f64 {
+        self.0.lock().unwrap().probability(entry)
+    }
+}
+
+/// Saves a vote and marks the entry as judged, then lets go of `manager`
+/// before teaching the vote to the classifier. Votes cast in `training` mode
+/// also log how much they moved the classifier.
+fn record_vote(
+    state: &StateApp,
+    mut manager: MutexGuard<FeedManager>,
+    queued: QueuedEntry,
+    is_liked: bool,
+    training: bool,
+) {
+    let rowid = manager
+        .save_vote("db/FeedHistory.db", &queued, is_liked)
+        .inspect_err(|e| eprintln!("failed to save vote: {}", e))
+        .ok();
+    let sample = if training {
+        manager
+            .to_see
+            .iter()
+            .rev()
+            .take(SHIFT_SAMPLE)
+            .map(|queued| queued.entry.clone())
+            .collect()
+    } else {
+        Vec::new()
+    };
+    let entry = queued.entry.clone();
+    manager.already_seen.push((queued, is_liked));
+    drop(manager);
+
+    let mut classifier = state.classifier.lock().unwrap();
+    if let Some(rowid) = rowid {
+        // votes racing each other may get here out of order
+        let trained_up_to = classifier.trained_up_to().max(rowid);
+        classifier.set_trained_up_to(trained_up_to);
+    }
+    if training {
+        let sample = sample.iter().collect::<Vec<_>>();
+        let shift = active::update_measuring(classifier.as_mut(), &entry, is_liked, &sample);
+        state.training.lock().unwrap().record(shift);
+    } else {
+        classifier.update(&entry, is_liked);
+    }
+    state.model_changed.notify_one();
+}
+
+#[derive(Serialize)]
+struct TrainingJson {
+    votes_seen: i32,
+    /// Votes still needed before the classifier starts filtering.
+    votes_to_prepare: i32,
+    is_prepared: bool,
+    log: TrainingLog,
+}
+
+/// Progress of the classifier, and how much votes cast in training mode
+/// moved it.
+#[get("/training")]
+fn training(state: &StateApp) -> Json<TrainingJson> {
+    let classifier = state.classifier.lock().unwrap();
+    Json(TrainingJson {
+        votes_seen: classifier.votes_seen(),
+        votes_to_prepare: classifier.votes_to_prepare(),
+        is_prepared: classifier.is_prepared(),
+        log: state.training.lock().unwrap().clone(),
+    })
+}
+
+#[derive(Serialize, Deserialize)]
+struct FilteredJson {
+    feed_url: String,
+    id: String,
+    title: String,
+    score: f64,
+    html: String,
+}
+
+/// Entries the classifier hid, those it came closest to showing first.
+#[get("/filtered")]
+fn list_filtered(state: &StateApp) -> Json<Vec<FilteredJson>> {
+    let manager = state.manager.lock().unwrap();
+    let mut filtered = manager
+        .filtered
+        .iter()
+        .map(|(queued, score)| FilteredJson {
+            feed_url: queued.feed_url.clone(),
+            id: queued.entry.id.clone(),
+            title: queued
+                .entry
+                .title
+                .as_ref()
+                .map_or(String::new(), |t| t.content.clone()),
+            score: *score,
+            html: queued.entry.into_html(),
+        })
+        .collect::<Vec<_>>();
+    filtered.sort_by(|a, b| b.score.total_cmp(&a.score));
+    Json(filtered)
+}
+
+#[derive(Serialize, Deserialize)]
+struct FilteredVoteReq {
+    feed_url: String,
+    id: String,
+    liked: bool,
+}
+
+/// Votes on a filtered entry, so a wrongly hidden one becomes training data.
+#[post("/filtered/vote", data = "<vote>")]
+fn vote_filtered(state: &StateApp, vote: Json<FilteredVoteReq>) -> Custom<Json<String>> {
+    let mut manager = state.manager.lock().unwrap();
+    let Some(queued) = manager.take_filtered(&vote.feed_url, &vote.id) else {
+        return Custom(Status::NotFound, Json("Entry not filtered".to_string()));
+    };
+    record_vote(state, manager, queued, vote.liked, false);
+    Custom(Status::Accepted, Json("Vote saved".to_string()))
+}
+
+#[derive(Serialize)]
+struct ExplainJson {
+    score: f64,
+    baseline: f64,
+    toward_like: Vec<(String, f64)>,
+    toward_dislike: Vec<(String, f64)>,
+}
+
+impl From<Explanation> for ExplainJson {
+    fn from(explanation: Explanation) -> Self {
+        ExplainJson {
+            score: explanation.score(),
+            baseline: explanation.baseline,
+            toward_like: explanation.toward_like(EXPLAINED_TOKENS),
+            toward_dislike: explanation.toward_dislike(EXPLAINED_TOKENS),
+        }
+    }
+}
+
+/// How the classifier scores a queued, filtered or already judged entry. The
+/// feed url and id are query parameters since entry ids are usually urls, and
+/// ids are only unique within a feed.
+#[get("/explain?<feed_url>&<id>")]
+fn explain(state: &StateApp, feed_url: &str, id: &str) -> Option<Json<ExplainJson>> {
+    let manager = state.manager.lock().unwrap();
+    let queued = manager
+        .to_see
+        .iter()
+        .chain(manager.already_seen.iter().map(|(queued, _)| queued))
+        .chain(manager.filtered.iter().map(|(queued, _)| queued))
+        .find(|queued| queued.key() == (feed_url, id))?;
+    let explanation = state.classifier.lock().unwrap().explain(&queued.entry);
+    Some(Json(explanation.into()))
+}
+
 #[derive(Serialize, Deserialize)]
 struct AddFeedReq {
     url: String,
 }
 
+/// Adds a feed and fetches it without holding on to the manager, like
+/// [`sync_periodically`], so syncs and votes meanwhile aren't lost.
 #[post("/add-feed", data = "<feed_url>")]
 async fn add_feed(state: &StateApp, feed_url: Json<AddFeedReq>) -> Custom<Json<String>> {
-    let mut manager = state.manager.lock().unwrap().clone();
-    if manager.get_feed(&feed_url.url).is_some() {
-        return Custom(Status::BadRequest, Json("Feed already added".to_string()));
-    }
-    let result = manager.new_feed(&feed_url.url).await;
+    let jobs = {
+        let mut manager = state.manager.lock().unwrap();
+        if manager.get_feed(&feed_url.url).is_some() {
+            return Custom(Status::BadRequest, Json("Feed already added".to_string()));
+        }
+        manager.start_new_feed(&feed_url.url)
+    };
+    let results = fetch_feeds(jobs).await;
 
-    *state.manager.lock().unwrap() = manager.clone();
+    let mut manager = state.manager.lock().unwrap();
+    let result = manager.finish_new_feed(&feed_url.url, results);
+    persist(&mut manager);
 
     if result.is_err() {
         return Custom(Status::BadRequest, Json("Error adding feed".to_string()));
@@ -81,30 +342,58 @@ async fn add_feed(state: &StateApp, feed_url: Json<AddFeedReq>) -> Custom<Json<S
 struct FeedJson {
     title: String,
     url: String,
+    status: Option<String>,
+    /// Whether the last sync went fine, "not modified" included.
+    ok: bool,
 }
 
 #[post("/delete-feed", data = "<feed_url>")]
 async fn delete_feed(state: &StateApp, feed_url: Json<AddFeedReq>) -> Custom<Json<String>> {
-    let mut manager = state.manager.lock().unwrap().clone();
-    manager
-        .purge_feed("db/FeedHistory.db", &feed_url.url)
-        .unwrap();
-    manager.sync().await;
-    *state.manager.lock().unwrap() = manager.clone();
-    assert!(state.manager.lock().unwrap().feeds.len() == manager.feeds.len());
+    let mut manager = state.manager.lock().unwrap();
+    if let Err(e) = manager.purge_feed("db/FeedHistory.db", &feed_url.url) {
+        return Custom(Status::InternalServerError, Json(e.to_string()));
+    }
+    persist(&mut manager);
     Custom(
         Status::Accepted,
         Json("Feed deletion task started".to_string()),
     )
 }
 
+#[derive(Serialize, Deserialize)]
+struct FeedIntervalReq {
+    url: String,
+    /// Seconds between refreshes, `None` to follow the feed's `ttl` again.
+    sync_interval: Option<u64>,
+}
+
+/// Overrides how often a feed is refreshed, from its next sync on.
+#[post("/feed-interval", data = "<req>")]
+fn feed_interval(state: &StateApp, req: Json<FeedIntervalReq>) -> Custom<Json<String>> {
+    let mut manager = state.manager.lock().unwrap();
+    if manager.get_feed(&req.url).is_none() {
+        return Custom(Status::NotFound, Json("Feed not found".to_string()));
+    }
+    manager
+        .feed_meta
+        .entry(req.url.clone())
+        .or_default()
+        .sync_interval = req.sync_interval;
+    persist(&mut manager);
+    Custom(Status::Accepted, Json("Sync interval saved".to_string()))
+}
+
 #[get("/feeds")]
 async fn list_feeds(state: &StateApp) -> Json<Vec<FeedJson>> {
     let mut feeds = vec![];
-    for (feed, url) in state.manager.lock().unwrap().feeds.iter() {
+    let manager = state.manager.lock().unwrap();
+    for (_, url) in manager.feeds.iter() {
+        let status = manager.last_sync.status(url);
         let f = FeedJson {
             title: url.clone(),
             url: url.clone(),
+            status: status.map(|s| s.to_string()),
+            ok: status.is_none_or(|s| s.is_ok()),
         };
         feeds.push(f);
     }
@@ -124,49 +413,247 @@ async fn index(_manager: &StateApp) -> Option<NamedFile> {
 
 struct StateAppS {
     manager: Arc<Mutex<FeedManager>>,
-    classifier: Arc<Mutex<NaiveBayesClassifier>>,
+    classifier: Arc<Mutex<Box<dyn Classifier>>>,
+    exploration: Exploration,
+    training: Mutex<TrainingLog>,
+    /// Wakes up [`rescore_on_change`].
+    model_changed: Arc<Notify>,
 }
 type StateApp = State<StateAppS>;
 #[launch]
 async fn rocket() -> _ {
-    let manager = Arc::new(Mutex::new(build_manager().await));
+    let rocket = rocket::build();
+    let sync_interval = rocket
+        .figment()
+        .extract_inner::<u64>("sync_interval")
+        .unwrap_or(DEFAULT_SYNC_INTERVAL);
+    let retention = rocket
+        .figment()
+        .extract_inner::<u64>("entry_retention")
+        .ok()
+        .map(Duration::from_secs);
+
+    let model_path = rocket
+        .figment()
+        .extract_inner::<String>("model_path")
+        .unwrap_or(DEFAULT_MODEL_PATH.to_string());
+    let tokenizer = rocket
+        .figment()
+        .extract_inner::<TokenizerConfig>("tokenizer")
+        .unwrap_or_default()
+        .build()
+        .unwrap_or_else(|e| {
+            eprintln!("bad tokenizer config, using the default one: {}", e);
+            TokenizerConfig::default().build().unwrap()
+        });
+    let model = rocket
+        .figment()
+        .extract_inner::<ModelKind>("model")
+        .unwrap_or_default();
+    let weighting = rocket
+        .figment()
+        .extract_inner::<Weighting>("weighting")
+        .unwrap_or_default();
+
+    let exploration = rocket
+        .figment()
+        .extract_inner::<Exploration>("exploration")
+        .unwrap_or_default();
+
+    // everything else only opens a db already at the latest schema
+    feed_sync::schema::upgrade("db/FeedHistory.db").expect("failed to upgrade the database");
+    let classifier = Arc::new(Mutex::new(load_classifier(
+        &model_path,
+        model,
+        weighting,
+        tokenizer,
+    )));
+    let mut manager = build_manager(retention);
+    manager.scorer = Some(ScoreHook(Arc::new(ClassifierScorer(Arc::clone(
+        &classifier,
+    )))));
+    let manager = Arc::new(Mutex::new(manager));
+    // the first pass scores what was queued before this start
+    let model_changed = Arc::new(Notify::new());
+    model_changed.notify_one();
     let state = StateAppS {
         manager: Arc::clone(&manager),
-        classifier: Arc::new(Mutex::new(
-            NaiveBayesClassifier::new("db/FeedHistory.db").unwrap(),
-        )),
+        classifier: Arc::clone(&classifier),
+        exploration,
+        training: Mutex::new(TrainingLog::default()),
+        model_changed: Arc::clone(&model_changed),
     };
     let closer = Arc::clone(&manager);
+    let syncer = Arc::clone(&manager);
+    let rescorer = (Arc::clone(&manager), Arc::clone(&classifier));
 
-    rocket::build()
+    rocket
         .manage(state)
         .mount(
             "/",
-            routes![index, next, add_feed, files, list_feeds, delete_feed],
+            routes![
+                index,
+                next,
+                explain,
+                training,
+                list_filtered,
+                vote_filtered,
+                add_feed,
+                files,
+                list_feeds,
+                delete_feed,
+                feed_interval
+            ],
         )
+        .attach(fairing::AdHoc::on_liftoff(
+            "periodic feed sync",
+            move |_rocket| {
+                Box::pin(async move {
+                    tokio::spawn(sync_periodically(
+                        syncer,
+                        Duration::from_secs(sync_interval),
+                    ));
+                })
+            },
+        ))
+        .attach(fairing::AdHoc::on_liftoff("rescoring", move |_rocket| {
+            Box::pin(async move {
+                let (manager, classifier) = rescorer;
+                tokio::spawn(rescore_on_change(manager, classifier, model_changed));
+            })
+        }))
         .attach(fairing::AdHoc::on_shutdown(
-            "saving already seen on db",
+            "saving feeds on db",
             |_rocket| {
                 Box::pin(async move {
-                    closer
-                        .lock()
-                        .unwrap()
-                        .save_already_seen("db/FeedHistory.db")
-                        .unwrap();
-
-                    closer
-                        .lock()
-                        .unwrap()
-                        .save_feeds("db/FeedHistory.db")
-                        .unwrap();
+                    persist(&mut closer.lock().unwrap());
                 })
             },
         ))
+        .attach(fairing::AdHoc::on_shutdown("saving model", |_rocket| {
+            Box::pin(async move {
+                let saved = classifier.lock().unwrap().save(Path::new(&model_path));
+                if let Err(e) = saved {
+                    eprintln!("failed to save model: {}", e);
+                }
+            })
+        }))
 }
 
-async fn build_manager() -> FeedManager {
+async fn sync_periodically(manager: Arc<Mutex<FeedManager>>, default_interval: Duration) {
+    let mut scheduler = Scheduler::new(default_interval);
+
+    loop {
+        let jobs = {
+            let manager = manager.lock().unwrap();
+            let due = scheduler.due(manager.feeds.iter().map(|(_, url)| url), Instant::now());
+            manager.sync_jobs(Some(&due))
+        };
+
+        if !jobs.is_empty() {
+            let results = fetch_feeds(jobs).await;
+            let mut manager = manager.lock().unwrap();
+            let report = manager.apply_sync(results);
+            for (url, status) in report.failures() {
+                eprintln!("failed to sync {}: {}", url, status);
+            }
+            scheduler.record(&manager, &report, Instant::now());
+            persist(&mut manager);
+        }
+
+        tokio::time::sleep(SYNC_TICK).await;
+    }
+}
+
+/// Scores queued entries again whenever the classifier changes, a batch at a
+/// time and never holding the manager and the classifier together, then
+/// publishes them all at once so `/next` can look scores up instead of
+/// computing them. Votes cast during a pass wake up another one.
+async fn rescore_on_change(
+    manager: Arc<Mutex<FeedManager>>,
+    classifier: Arc<Mutex<Box<dyn Classifier>>>,
+    changed: Arc<Notify>,
+) {
+    loop {
+        changed.notified().await;
+        let jobs = manager.lock().unwrap().scoring_jobs();
+
+        let mut scores = HashMap::with_capacity(jobs.len());
+        for batch in jobs.chunks(RESCORE_BATCH) {
+            {
+                let classifier = classifier.lock().unwrap();
+                for (key, entry) in batch {
+                    scores.insert(key.clone(), classifier.probability(entry));
+                }
+            }
+            tokio::task::yield_now().await;
+        }
+        manager.lock().unwrap().publish_scores(scores);
+    }
+}
+
+fn persist(manager: &mut FeedManager) {
+    let saved = manager
+        .save_feeds("db/FeedHistory.db")
+        .and_then(|_| manager.save_entries("db/FeedHistory.db"));
+    if let Err(e) = saved {
+        eprintln!("failed to save feeds: {}", e);
+    }
+}
+
+/// Starts from the saved model when there is one of kind `model` trained
+/// with `weighting` and `tokenizer` on the votes still in the db, then
+/// learns the votes cast since it was saved.
+fn load_classifier(
+    model_path: &str,
+    model: ModelKind,
+    weighting: Weighting,
+    tokenizer: Box<dyn Tokenizer>,
+) -> Box<dyn Classifier> {
+    let path = Path::new(model_path);
+    let mut classifier = match classifier::load(path) {
+        Ok(classifier)
+            if classifier.kind() != model
+                || classifier.weighting().is_some_and(|w| w != weighting)
+                || classifier.tokenizer().config() != tokenizer.config() =>
+        {
+            eprintln!("model, weighting or tokenizer settings changed, retraining the model");
+            model.build_weighted(tokenizer, weighting)
+        }
+        Ok(classifier)
+            if !holds_learned_votes(
+                "db/FeedHistory.db",
+                classifier.trained_up_to(),
+                classifier.votes_seen(),
+            )
+            .unwrap() =>
+        {
+            eprintln!(
+                "the votes in the db changed since the model was saved, retraining the model"
+            );
+            model.build_weighted(tokenizer, weighting)
+        }
+        Ok(classifier) => classifier,
+        Err(e) => {
+            if path.exists() {
+                eprintln!("failed to load model, retraining: {}", e);
+            }
+            model.build_weighted(tokenizer, weighting)
+        }
+    };
+    let votes = votes_since("db/FeedHistory.db", classifier.trained_up_to()).unwrap();
+    classifier.fold_in(votes);
+    classifier
+}
+
+/// Restores feeds and the queue from the db. Fetching is left to the
+/// periodic sync, which treats every feed as due on its first pass.
+fn build_manager(retention: Option<Duration>) -> FeedManager {
     let mut manager = FeedManager::new();
+    if let Some(retention) = retention {
+        manager.retention = retention;
+    }
     manager.load_feeds_from_db("db/FeedHistory.db").unwrap();
-    manager.sync().await;
+    manager.load_entries_from_db("db/FeedHistory.db").unwrap();
     manager
 }